@@ -0,0 +1,356 @@
+// Runs a scene without a window and writes what happens in every step to a file, for offline analysis
+
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+
+use macroquad::math::Vec2;
+
+use verlet::simulation::{LinkBreak, Scene, Simulation};
+
+
+const USAGE: &str = "\
+Usage: verlet-cli <scene> [options]
+
+  --steps N            steps to run, or
+  --duration S         simulated seconds to run, 10 by default
+  --delta S            seconds per step, 1/240 by default
+  --every N            only write every Nth step and the last one, 1 by default.
+                       The breaks of the skipped steps are written with the next written step
+  --out PATH           where to write, as CSV if it ends with .csv and as JSON Lines otherwise.
+                       The name of the scene with .jsonl by default
+  --sweep NAME=A,B,..  runs the scene once for every value, in every combination with the other sweeps,
+                       and writes every run to its own file with the values in its name.
+                       NAME is stiffness, damping, max-stress (N), substeps or gravity (m/s²)";
+const DEFAULT_DURATION: f32 = 10.0;
+const DEFAULT_DELTA: f32 = 1.0 / 240.0;
+
+
+// What a sweep changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Parameter {
+    // Of every link
+    Stiffness,
+    Damping,
+    MaxStress,
+    // Every step is split into this many steps of the same length
+    Substeps,
+    // Downwards acceleration
+    Gravity,
+}
+impl Parameter {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stiffness" => Some(Parameter::Stiffness),
+            "damping" => Some(Parameter::Damping),
+            "max-stress" => Some(Parameter::MaxStress),
+            "substeps" => Some(Parameter::Substeps),
+            "gravity" => Some(Parameter::Gravity),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+struct Sweep {
+    name: String,
+    parameter: Parameter,
+    // As written in the arguments, for the file names, and as numbers
+    values: Vec<(String, f32)>,
+}
+impl Sweep {
+    fn parse(arg: &str) -> Result<Self, String> {
+        let (name, values) = arg.split_once('=').ok_or_else(|| format!("Sweeps look like NAME=A,B,C, not {}", arg))?;
+        let parameter = Parameter::from_name(name).ok_or_else(|| format!("Cant sweep over {}", name))?;
+        let values = values.split(',').map(|value| {
+            let number = value.parse::<f32>().ok().filter(|number| number.is_finite());
+            let number = match parameter {
+                Parameter::Substeps => number.filter(|number| number.fract() == 0.0 && *number >= 1.0),
+                _ => number,
+            };
+            number.map(|number| (value.to_owned(), number)).ok_or_else(|| format!("Invalid value for {}: {}", name, value))
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Sweep { name: name.to_owned(), parameter, values })
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    JsonLines,
+}
+
+
+#[derive(Debug)]
+struct Options {
+    scene: PathBuf,
+    steps: usize,
+    delta: f32,
+    every: usize,
+    out: PathBuf,
+    sweeps: Vec<Sweep>,
+}
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut scene = None;
+        let (mut steps, mut duration, mut delta, mut every, mut out) = (None, None, DEFAULT_DELTA, 1, None);
+        let mut sweeps = vec![];
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if scene.replace(PathBuf::from(&arg)).is_some() {
+                    return Err(format!("Only one scene can be run at a time, got another one: {}", arg));
+                }
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            let invalid = || format!("Invalid value for {}: {}", arg, value);
+            match arg.as_str() {
+                "--steps" => steps = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                "--duration" => duration = Some(value.parse::<f32>().ok().filter(|duration| duration.is_finite() && *duration >= 0.0).ok_or_else(invalid)?),
+                "--delta" => delta = value.parse::<f32>().ok().filter(|delta| delta.is_finite() && *delta > 0.0).ok_or_else(invalid)?,
+                "--every" => every = value.parse::<usize>().ok().filter(|every| *every > 0).ok_or_else(invalid)?,
+                "--out" => out = Some(PathBuf::from(value)),
+                "--sweep" => sweeps.push(Sweep::parse(&value)?),
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        let scene = scene.ok_or("No scene given")?;
+        let steps = steps.unwrap_or_else(|| (duration.unwrap_or(DEFAULT_DURATION) / delta).round() as usize);
+        let out = out.unwrap_or_else(|| scene.with_extension("jsonl").file_name().map(PathBuf::from).unwrap_or_else(|| "run.jsonl".into()));
+        Ok(Options { scene, steps, delta, every, out, sweeps })
+    }
+
+
+    fn format(&self) -> Format {
+        match self.out.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::JsonLines,
+        }
+    }
+
+
+    // Every combination of the sweep values, as (sweep index, value index). Just one empty run without sweeps
+    fn variants(&self) -> Vec<Vec<(usize, usize)>> {
+        self.sweeps.iter().enumerate().fold(vec![vec![]], |variants, (sweep_idx, sweep)| {
+            variants.iter().flat_map(|variant| (0..sweep.values.len()).map(move |value_idx| {
+                let mut variant = variant.clone();
+                variant.push((sweep_idx, value_idx));
+                variant
+            })).collect()
+        })
+    }
+
+
+    // The output path with the values of the variant added to the file name, like cloth_stiffness-0.5.csv
+    fn variant_path(&self, variant: &[(usize, usize)]) -> PathBuf {
+        let stem = self.out.file_stem().map_or("run".into(), |stem| stem.to_string_lossy());
+        let suffix = variant.iter()
+            .map(|(sweep_idx, value_idx)| format!("_{}-{}", self.sweeps[*sweep_idx].name, self.sweeps[*sweep_idx].values[*value_idx].0))
+            .collect::<String>();
+        let mut name = format!("{}{}", stem, suffix);
+        if let Some(ext) = self.out.extension() {
+            name = format!("{}.{}", name, ext.to_string_lossy());
+        }
+        self.out.with_file_name(name)
+    }
+}
+
+
+fn main() {
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(2);
+    });
+    let scene = Scene::load(&options.scene).unwrap_or_else(|err| {
+        eprintln!("Could not load the scene {}: {}", options.scene.display(), err);
+        std::process::exit(1);
+    });
+    for variant in options.variants() {
+        let path = options.variant_path(&variant);
+        if let Err(err) = run(&options, &scene, &variant, &path) {
+            eprintln!("Could not write {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        println!("Ran {} steps into {}", options.steps, path.display());
+    }
+}
+
+
+// Steps the scene with the values of the variant, and writes the steps to the path
+fn run(options: &Options, scene: &Scene, variant: &[(usize, usize)], path: &Path) -> io::Result<()> {
+    let mut scene = scene.clone();
+    let mut substeps = 1;
+    let mut gravity = None;
+    for (sweep_idx, value_idx) in variant.iter() {
+        let sweep = &options.sweeps[*sweep_idx];
+        let value = sweep.values[*value_idx].1;
+        match sweep.parameter {
+            Parameter::Stiffness => scene.links.iter_mut().for_each(|link| *link = link.clone().stiffness(value)),
+            Parameter::Damping => scene.links.iter_mut().for_each(|link| *link = link.clone().damping(value)),
+            Parameter::MaxStress => scene.links.iter_mut().for_each(|link| *link = link.clone().max_stress(value)),
+            Parameter::Substeps => substeps = value as usize,
+            Parameter::Gravity => gravity = Some(value),
+        }
+    }
+
+    let mut simulation = Simulation::new();
+    simulation.measure_energy = true;
+    simulation.load_scene(&scene);
+    if let Some(gravity) = gravity {
+        simulation.set_force(Vec2::new(0.0, gravity));
+    }
+    // Same results every time, so runs can be compared
+    let delta = options.delta / substeps as f32;
+    simulation.set_deterministic(Some(delta));
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    let format = options.format();
+    if format == Format::Csv {
+        writeln!(out, "step,time,kind,index,x,y,value")?;
+    }
+    let mut breaks = vec![];
+    for step in 1..=options.steps {
+        for _ in 0..substeps {
+            simulation.step_headless(delta);
+            breaks.extend_from_slice(simulation.link_breaks());
+        }
+        if step % options.every == 0 || step == options.steps {
+            match format {
+                Format::Csv => write_csv(&mut out, step, &simulation, &breaks)?,
+                Format::JsonLines => write_json(&mut out, step, &simulation, &breaks)?,
+            }
+            breaks.clear();
+        }
+    }
+    out.flush()
+}
+
+
+// One row per value: positions of the points, strains of the links, breaks with their load (N) where they happened,
+// and the energies (J) and momentum (kg*m/s)
+fn write_csv(out: &mut impl Write, step: usize, simulation: &Simulation, breaks: &[LinkBreak]) -> io::Result<()> {
+    let energy = simulation.energy().last().copied().unwrap_or_default();
+    let time = energy.time;
+    let positions = simulation.positions();
+    for (i, pos) in positions.iter().enumerate() {
+        writeln!(out, "{},{},point,{},{},{},", step, time, i, pos.x, pos.y)?;
+    }
+    for (i, strain) in simulation.link_strains().enumerate() {
+        writeln!(out, "{},{},link,{},,,{}", step, time, i, strain)?;
+    }
+    for link_break in breaks.iter() {
+        let pos = (positions[link_break.from_idx] + positions[link_break.to_idx]) * 0.5;
+        writeln!(out, "{},{},break,{},{},{},{}", step, time, link_break.link_idx, pos.x, pos.y, link_break.load)?;
+    }
+    writeln!(out, "{},{},kinetic,,,,{}", step, time, energy.kinetic)?;
+    writeln!(out, "{},{},potential,,,,{}", step, time, energy.potential)?;
+    writeln!(out, "{},{},elastic,,,,{}", step, time, energy.elastic)?;
+    writeln!(out, "{},{},momentum,,{},{},", step, time, energy.momentum.x, energy.momentum.y)
+}
+
+
+// One object per step
+fn write_json(out: &mut impl Write, step: usize, simulation: &Simulation, breaks: &[LinkBreak]) -> io::Result<()> {
+    let energy = simulation.energy().last().copied().unwrap_or_default();
+    let positions = simulation.positions().iter()
+        .map(|pos| format!("[{},{}]", number(pos.x), number(pos.y)))
+        .collect::<Vec<_>>().join(",");
+    let strains = simulation.link_strains().map(number).collect::<Vec<_>>().join(",");
+    let breaks = breaks.iter().map(|link_break| format!(
+        r#"{{"time":{},"link":{},"from":{},"to":{},"load":{}}}"#,
+        number(link_break.time), link_break.link_idx, link_break.from_idx, link_break.to_idx, number(link_break.load),
+    )).collect::<Vec<_>>().join(",");
+    writeln!(out,
+        r#"{{"step":{},"time":{},"energy":{{"kinetic":{},"potential":{},"elastic":{},"total":{},"momentum":[{},{}]}},"positions":[{}],"strains":[{}],"breaks":[{}]}}"#,
+        step, number(energy.time), number(energy.kinetic), number(energy.potential), number(energy.elastic), number(energy.total()),
+        number(energy.momentum.x), number(energy.momentum.y), positions, strains, breaks,
+    )
+}
+
+
+// JSON has no NaN or infinity
+fn number(value: f32) -> String {
+    if value.is_finite() { value.to_string() } else { "null".to_owned() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Format, Options, Parameter, Sweep, DEFAULT_DELTA};
+
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+
+    #[test]
+    fn defaults_come_from_the_scene() {
+        let options = parse("scenes/cloth.txt").unwrap();
+        assert_eq!(options.scene, PathBuf::from("scenes/cloth.txt"));
+        assert_eq!(options.steps, 2400);
+        assert_eq!(options.delta, DEFAULT_DELTA);
+        assert_eq!(options.every, 1);
+        assert_eq!(options.out, PathBuf::from("cloth.jsonl"));
+        assert_eq!(options.format(), Format::JsonLines);
+    }
+
+
+    #[test]
+    fn options_are_read() {
+        let options = parse("--duration 2 --delta 0.01 scene.txt --every 10 --out runs/out.CSV").unwrap();
+        assert_eq!(options.steps, 200);
+        assert_eq!(options.every, 10);
+        assert_eq!(options.format(), Format::Csv);
+        // Steps win over the duration
+        assert_eq!(parse("scene.txt --duration 2 --steps 7").unwrap().steps, 7);
+    }
+
+
+    #[test]
+    fn invalid_options_are_errors() {
+        assert!(parse("--steps 10").is_err());
+        assert!(parse("a.txt b.txt").is_err());
+        assert!(parse("scene.txt --steps").is_err());
+        assert!(parse("scene.txt --delta 0").is_err());
+        assert!(parse("scene.txt --every 0").is_err());
+        assert!(parse("scene.txt --fast 1").is_err());
+        assert!(Sweep::parse("stiffness").is_err());
+        assert!(Sweep::parse("color=1,2").is_err());
+        assert!(Sweep::parse("substeps=1,2.5").is_err());
+        assert!(Sweep::parse("gravity=1,inf").is_err());
+    }
+
+
+    #[test]
+    fn sweeps_run_every_combination() {
+        let options = parse("scene.txt --sweep stiffness=0.1,0.5 --sweep substeps=1,2,4").unwrap();
+        assert_eq!(options.sweeps[1].parameter, Parameter::Substeps);
+        assert_eq!(options.sweeps[1].values[2], ("4".to_owned(), 4.0));
+        let variants = options.variants();
+        assert_eq!(variants.len(), 6);
+        assert_eq!(variants[0], vec![(0, 0), (1, 0)]);
+        assert_eq!(variants[5], vec![(0, 1), (1, 2)]);
+        // No sweeps is one run
+        assert_eq!(parse("scene.txt").unwrap().variants(), vec![vec![]]);
+    }
+
+
+    #[test]
+    fn variant_paths_name_the_values() {
+        let options = parse("scene.txt --out runs/cloth.csv --sweep stiffness=0.1,0.5 --sweep max-stress=1e3").unwrap();
+        assert_eq!(options.variant_path(&[(0, 1), (1, 0)]), PathBuf::from("runs/cloth_stiffness-0.5_max-stress-1e3.csv"));
+        assert_eq!(options.variant_path(&[]), PathBuf::from("runs/cloth.csv"));
+        let options = parse("scene.txt --out run --sweep gravity=9.81").unwrap();
+        assert_eq!(options.variant_path(&[(0, 0)]), PathBuf::from("run_gravity-9.81"));
+    }
+}
@@ -1,11 +1,6 @@
-use std::time::{Duration, Instant};
-
 use macroquad::prelude::*;
 
-mod simulation;
-use simulation::{IKChain, Link, Point, Simulation};
-
-pub mod ui;
+use verlet::simulation::{Camera, DebugOverlays, FrameSink, Heatmap, IKChain, InputState, Link, Point, Scene, Simulation};
 
 
 
@@ -19,13 +14,46 @@ fn window_conf() -> Conf {
 }
 
 
-#[macroquad::main(window_conf)]
-async fn main() {
+fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     std::env::set_var("RUST_BACKTRACE", "1");
 
     //rayon::ThreadPoolBuilder::new().num_threads(2).build_global().unwrap();
 
+    // Render to files without opening a window with `--export <path>`, see export_frames
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = arg_value("--export") {
+        export_frames(&path);
+        return;
+    }
+    macroquad::Window::from_config(window_conf(), run());
+}
+
+
+async fn run() {
+    let mut simulation = load_simulation();
+
+    // Step the simulation on its own thread, so slow frames dont slow down the simulated time
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::args().any(|arg| arg == "--sim-thread") {
+        // The thread does 240 steps per second, so this runs in real time
+        simulation.run_in_thread(1.0 / 240.0);
+    }
+
+    // Frame times, also on the web
+    simulation.debug.profiler = true;
+
+    loop {
+        clear_background(BLACK);
+        simulation.update(1.0 / 60.0);
+
+        next_frame().await
+    }
+}
+
+
+// The scene of `--scene <path>`, or the default chain
+fn load_simulation() -> Simulation {
     let mut simulation = Simulation::new();
     // let width = 100;
     // let height = 22;
@@ -52,42 +80,124 @@ async fn main() {
     //     }
     // }
 
-    let chain_start_pos = Vec2::new(100.0, 500.0);
-    let chain_end_pos = Vec2::new(1800.0, 500.0);
-    let num_links = 20;
-    let diff = (chain_end_pos - chain_start_pos) / num_links as f32;
-    let link_length = diff.length();
-    simulation.add_point(Point::new(chain_start_pos).fixed());
-    for i in 1..=num_links {
-        simulation.add_point(Point::new(chain_start_pos + diff * i as f32));
-        simulation.add_link(Link::new(i - 1, i).max_length(link_length).stiffness(0.01).damping(0.9));
+    // Load a scene with `--scene <path>` instead of the default chain
+    let scene_path = arg_value("--scene");
+    let scene = scene_path.and_then(|path| match Scene::load(&path) {
+        Ok(scene) => Some(scene),
+        Err(err) => {
+            eprintln!("Could not load the scene {}: {}", path, err);
+            None
+        },
+    });
+    if let Some(scene) = scene {
+        simulation.load_scene(&scene);
+    } else {
+        // In meters, the window shows 19.2 x 10.8 m
+        let chain_start_pos = Vec2::new(1.0, 5.0);
+        let chain_end_pos = Vec2::new(18.0, 5.0);
+        let num_links = 20;
+        let diff = (chain_end_pos - chain_start_pos) / num_links as f32;
+        let link_length = diff.length();
+        simulation.add_point(Point::new(chain_start_pos).fixed());
+        for i in 1..=num_links {
+            simulation.add_point(Point::new(chain_start_pos + diff * i as f32));
+            simulation.add_link(Link::new(i - 1, i).max_length(link_length).stiffness(0.01).damping(0.9));
+        }
+        simulation.add_ik_chain(IKChain::new((0..num_links).collect()));
     }
-    simulation.add_ik_chain(IKChain::new((0..num_links).collect()));
+    simulation
+}
 
-    let mut time_sum = Duration::ZERO;
-    let mut num_iterations = 0;
 
-    loop {
-        clear_background(BLACK);
-        if !simulation.paused {
-            num_iterations += 1;
+// Renders the simulation into PNG files or a GIF on the CPU, stepping it the same as update does.
+// The options are:
+//   --size WxH          pixels of the frames, 1280x720 by default
+//   --fps N             frames per second of simulated time, 30 by default
+//   --frames N          how many frames, or
+//   --duration S        how many simulated seconds, 5 by default
+//   --camera X,Y,ZOOM   world position in the center and zoom, the bounds fill the frame by default
+//   --overlays A,B      debug overlays: fill, velocities, strain, load, forces, ik, labels, bounds, grid.
+//                       Only fill by default, none for an empty list
+#[cfg(not(target_arch = "wasm32"))]
+fn export_frames(path: &str) {
+    let size = parse_arg("--size", Vec2::new(1280.0, 720.0), |value| {
+        let (width, height) = value.split_once('x')?;
+        let size = Vec2::new(width.parse::<u16>().ok()? as f32, height.parse::<u16>().ok()? as f32);
+        (size.min_element() > 0.0).then_some(size)
+    });
+    let fps = parse_arg("--fps", 30.0, |value| value.parse::<f32>().ok().filter(|fps| fps.is_finite() && *fps > 0.0));
+    let duration = parse_arg("--duration", 5.0, |value| value.parse::<f32>().ok().filter(|duration| duration.is_finite() && *duration >= 0.0));
+    let frames = parse_arg("--frames", (duration * fps).round() as usize, |value| value.parse().ok());
+    let overlays = parse_arg("--overlays", DebugOverlays { fill: true, ..Default::default() }, |value| {
+        let mut overlays = DebugOverlays::default();
+        for name in value.split(',').filter(|name| !name.is_empty()) {
+            match name {
+                "fill" => overlays.fill = true,
+                "velocities" => overlays.velocities = true,
+                "strain" => overlays.heatmap = Heatmap::Strain,
+                "load" => overlays.heatmap = Heatmap::Load,
+                "forces" => overlays.forces = true,
+                "ik" => overlays.ik = true,
+                "labels" => overlays.labels = true,
+                "bounds" => overlays.bounds = true,
+                "grid" => overlays.grid = true,
+                _ => return None,
+            }
         }
-        #[cfg(not(target_arch = "wasm32"))]
-        if num_iterations > 0 {
-            let font_size: u16 = 40;
-            let text = format!("Avg. update time: {time:.*} ms", 3, time=(time_sum.as_millis() as f64 / num_iterations as f64));
-            let dims = measure_text(&text, None, font_size, 1.0);
-            draw_text(&text, screen_width()/2.0 - dims.width/2.0, dims.height, font_size as f32, GRAY);
+        Some(overlays)
+    });
+
+    let mut simulation = load_simulation();
+    simulation.debug = overlays;
+    simulation.camera.screen_size = size;
+    simulation.camera.fit(simulation.bounds());
+    let fitted = simulation.camera.clone();
+    simulation.camera = parse_arg("--camera", fitted.clone(), |value| {
+        let mut numbers = value.split(',').map(|number| number.parse::<f32>().ok().filter(|number| number.is_finite()));
+        let (Some(x), Some(y), Some(zoom), None) = (numbers.next()?, numbers.next()?, numbers.next()?, numbers.next()) else {
+            return None;
+        };
+        let mut camera = Camera::new(Vec2::new(x, y), zoom);
+        camera.pixels_per_meter = fitted.pixels_per_meter;
+        camera.screen_size = size;
+        Some(camera)
+    });
+
+    let mut sink = FrameSink::create(path, fps).unwrap_or_else(|err| {
+        eprintln!("Could not create {}: {}", path, err);
+        std::process::exit(1);
+    });
+    // Steps of at most 1/60 s like in the window, that add up to one frame
+    let steps = (60.0 / fps).ceil();
+    // The mouse rests in the middle, IK chains reach for it
+    let input = InputState::at(size * 0.5).screen_size(size);
+    for _ in 0..frames {
+        for _ in 0..steps as usize {
+            simulation.update_headless(1.0 / fps / steps, input.clone());
         }
-        
-        #[cfg(not(target_arch = "wasm32"))]
-        let start = Instant::now();
-        simulation.update(1.0 / 180.0);
-        #[cfg(not(target_arch = "wasm32"))]
-        if !simulation.paused {
-            time_sum += start.elapsed();
+        if let Err(err) = sink.push(&simulation.render_image(BLACK)) {
+            eprintln!("Could not write frame {} to {}: {}", sink.frames(), path, err);
+            std::process::exit(1);
         }
-
-        next_frame().await
     }
-}
\ No newline at end of file
+    println!("Exported {} frames to {}", sink.frames(), path);
+}
+
+
+// Value after the option, like the path of `--scene <path>`
+fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+
+// Value of the option or the default if it isnt given. Exits if the value cant be parsed
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_arg<T>(name: &str, default: T, parse: impl Fn(&str) -> Option<T>) -> T {
+    let Some(value) = arg_value(name) else {
+        return default;
+    };
+    parse(&value).unwrap_or_else(|| {
+        eprintln!("Invalid value for {}: {}", name, value);
+        std::process::exit(2);
+    })
+}
@@ -0,0 +1,293 @@
+use macroquad::prelude::*;
+
+use super::{Link, Point, Renderer, Simulation, Tool, POINT_RADIUS, SELECT_COLOR};
+
+
+// What new points and links are made of
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub stiffness: f32,
+    pub damping: f32,
+    // Mass (kg) of every new point
+    pub mass: f32,
+    pub color: Color,
+}
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            stiffness: 0.01,
+            damping: 0.9,
+            mass: 1.0,
+            color: WHITE,
+        }
+    }
+}
+impl Material {
+    pub fn point(&self, position: Vec2) -> Point {
+        Point::new(position).mass(self.mass).color(self.color)
+    }
+
+    // Link that can't get longer than the given length
+    pub fn link(&self, from_idx: usize, to_idx: usize, length: f32) -> Link {
+        Link::new(from_idx, to_idx).max_length(length).stiffness(self.stiffness).damping(self.damping)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct BuildSettings {
+    pub material: Material,
+    // Distance (m) between the points of ropes and cloth
+    pub spacing: f32,
+    // Fix the top row of new cloth, so it hangs down
+    pub pin_top: bool,
+}
+impl Default for BuildSettings {
+    fn default() -> Self {
+        Self {
+            material: Material::default(),
+            spacing: 0.25,
+            pin_top: true,
+        }
+    }
+}
+impl BuildSettings {
+    // Spacing that ropes and cloth are actually built with, tiny spacings would make millions of points
+    pub(super) fn spacing(&self) -> f32 {
+        self.spacing.max(0.01)
+    }
+}
+
+
+// What the left mouse button is doing with the build tools
+#[derive(Debug, Clone, Default)]
+pub(super) enum BuildGesture {
+    #[default]
+    None,
+    // Dragging a new link away from the point
+    Link(usize),
+    // Positions of the rope so far, starting at an existing point if there was one
+    Rope(Vec<Vec2>, Option<usize>),
+    // Corner where the cloth rectangle started
+    Cloth(Vec2),
+}
+
+
+impl Simulation {
+    pub(super) fn handle_build_tools(&mut self) {
+        let mouse_pos = self.mouse_position();
+        if !matches!(self.tool, Tool::Build | Tool::Rope | Tool::Cloth) {
+            self.build_gesture = BuildGesture::None;
+            return;
+        }
+        let hovered = self.nearest_point(mouse_pos, self.camera.pixels(POINT_RADIUS));
+
+        if self.input.is_pressed(MouseButton::Left) && !self.input.mouse_over_ui {
+            self.build_gesture = match (self.tool, hovered) {
+                (Tool::Build, Some(point_idx)) => BuildGesture::Link(point_idx),
+                (Tool::Build, None) => {
+                    self.add_point(self.build_settings.material.point(mouse_pos));
+                    BuildGesture::None
+                },
+                (Tool::Rope, Some(point_idx)) => BuildGesture::Rope(vec![self.snapshot.positions[point_idx]], Some(point_idx)),
+                (Tool::Rope, None) => BuildGesture::Rope(vec![mouse_pos], None),
+                _ => BuildGesture::Cloth(mouse_pos),
+            };
+        }
+
+        let released = !self.input.is_down(MouseButton::Left);
+        let material = self.build_settings.material;
+        let spacing = self.build_settings.spacing();
+        self.build_gesture = match std::mem::take(&mut self.build_gesture) {
+            BuildGesture::None => BuildGesture::None,
+            BuildGesture::Link(from_idx) => {
+                let Some(from) = self.snapshot.positions.get(from_idx).copied() else {
+                    return;
+                };
+                if !released {
+                    BuildGesture::Link(from_idx)
+                } else {
+                    // Dropping the link on empty space creates a new point for its end
+                    let (to_idx, to) = match hovered {
+                        Some(point_idx) => (point_idx, self.snapshot.positions[point_idx]),
+                        None => {
+                            self.add_point(material.point(mouse_pos));
+                            (self.num_points - 1, mouse_pos)
+                        },
+                    };
+                    if to_idx != from_idx {
+                        self.add_link(material.link(from_idx, to_idx, from.distance(to)));
+                    }
+                    BuildGesture::None
+                }
+            },
+            BuildGesture::Rope(mut positions, start_idx) => {
+                // Place the next point every `spacing` meters along the mouse path
+                while let Some(last) = positions.last().copied() {
+                    if last.distance(mouse_pos) < spacing {
+                        break;
+                    }
+                    positions.push(last + (mouse_pos - last).normalize() * spacing);
+                }
+                if !released {
+                    BuildGesture::Rope(positions, start_idx)
+                } else {
+                    self.add_rope(&positions, start_idx, hovered);
+                    BuildGesture::None
+                }
+            },
+            BuildGesture::Cloth(start) => {
+                let (min, columns, rows) = cloth_grid(start, mouse_pos, spacing);
+                if !released {
+                    BuildGesture::Cloth(start)
+                } else {
+                    if columns > 1 || rows > 1 {
+                        self.add_cloth(min, columns, rows, spacing);
+                    }
+                    BuildGesture::None
+                }
+            },
+        };
+    }
+
+
+    // The link, rope or cloth that is being dragged
+    pub(super) fn draw_build_preview(&self, renderer: &mut impl Renderer, mouse_pos: Vec2) {
+        let pixel = self.camera.pixels(1.0);
+        match &self.build_gesture {
+            BuildGesture::None => (),
+            BuildGesture::Link(from_idx) => {
+                if let Some(from) = self.snapshot.positions.get(*from_idx) {
+                    renderer.line(*from, mouse_pos, 2.0 * pixel, SELECT_COLOR);
+                }
+            },
+            BuildGesture::Rope(positions, _) => {
+                for pair in positions.windows(2) {
+                    renderer.line(pair[0], pair[1], 2.0 * pixel, SELECT_COLOR);
+                }
+                for pos in positions.iter() {
+                    renderer.circle_lines(*pos, POINT_RADIUS * pixel, pixel, SELECT_COLOR);
+                }
+            },
+            BuildGesture::Cloth(start) => {
+                let spacing = self.build_settings.spacing();
+                let (min, columns, rows) = cloth_grid(*start, mouse_pos, spacing);
+                renderer.rect_lines(min, start.max(mouse_pos) - min, pixel, SELECT_COLOR);
+                for y in 0..rows {
+                    for x in 0..columns {
+                        let pos = min + Vec2::new(x as f32, y as f32) * spacing;
+                        renderer.circle(pos, 2.0 * pixel, SELECT_COLOR);
+                    }
+                }
+            },
+        }
+    }
+
+
+    // A chain of points through the positions. The first and last positions are replaced
+    // by the existing points at the ends, if the rope starts or ends on one
+    fn add_rope(&mut self, positions: &[Vec2], start_idx: Option<usize>, end_idx: Option<usize>) {
+        let material = self.build_settings.material;
+        let new_positions = &positions[start_idx.map_or(0, |_| 1)..];
+        let first_idx = self.num_points;
+        self.add_points(&new_positions.iter().map(|pos| material.point(*pos)).collect::<Vec<_>>());
+
+        // (point index, position) of every point along the rope
+        let mut chain = start_idx.map(|idx| (idx, positions[0])).into_iter()
+            .chain(new_positions.iter().enumerate().map(|(i, pos)| (first_idx + i, *pos)))
+            .collect::<Vec<_>>();
+        if let Some(end_idx) = end_idx.filter(|idx| Some(*idx) != start_idx) {
+            chain.push((end_idx, self.snapshot.positions[end_idx]));
+        }
+        for pair in chain.windows(2) {
+            self.add_link(material.link(pair[0].0, pair[1].0, pair[0].1.distance(pair[1].1)));
+        }
+    }
+
+
+    // A grid of points linked to their right and lower neighbors
+    fn add_cloth(&mut self, top_left: Vec2, columns: usize, rows: usize, spacing: f32) {
+        let BuildSettings { material, pin_top, .. } = self.build_settings;
+        let first_idx = self.num_points;
+        let mut points = vec![];
+        for y in 0..rows {
+            for x in 0..columns {
+                let mut point = material.point(top_left + Vec2::new(x as f32, y as f32) * spacing);
+                if y == 0 && pin_top {
+                    point = point.fixed();
+                }
+                points.push(point);
+            }
+        }
+        self.add_points(&points);
+        for y in 0..rows {
+            for x in 0..columns {
+                let idx = first_idx + y * columns + x;
+                if x < columns - 1 {
+                    self.add_link(material.link(idx, idx + 1, spacing));
+                }
+                if y < rows - 1 {
+                    self.add_link(material.link(idx, idx + columns, spacing));
+                }
+            }
+        }
+    }
+}
+
+
+// Top left corner and number of columns and rows of the cloth between the two corners
+fn cloth_grid(start: Vec2, end: Vec2, spacing: f32) -> (Vec2, usize, usize) {
+    let min = start.min(end);
+    let max = start.max(end);
+    let columns = ((max.x - min.x) / spacing) as usize + 1;
+    let rows = ((max.y - min.y) / spacing) as usize + 1;
+    (min, columns, rows)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::*;
+
+    use super::cloth_grid;
+    use crate::simulation::{InputState, Simulation, Tool};
+
+
+    #[test]
+    fn cloth_grid_fits_into_the_rectangle() {
+        let (min, columns, rows) = cloth_grid(Vec2::new(1.0, 1.0), Vec2::new(2.1, 1.6), 0.25);
+        assert_eq!(min, Vec2::new(1.0, 1.0));
+        assert_eq!((columns, rows), (5, 3));
+        // Dragging from any corner gives the same grid
+        assert_eq!(cloth_grid(Vec2::new(2.1, 1.6), Vec2::new(1.0, 1.0), 0.25), (min, columns, rows));
+        assert_eq!(cloth_grid(Vec2::new(1.0, 1.6), Vec2::new(2.1, 1.0), 0.25), (min, columns, rows));
+    }
+
+
+    #[test]
+    fn cloth_grid_of_a_click_is_one_point() {
+        assert_eq!(cloth_grid(Vec2::ONE, Vec2::ONE, 0.25), (Vec2::ONE, 1, 1));
+    }
+
+
+    #[test]
+    fn dragged_cloth_has_the_spacing_of_the_settings() {
+        let mut simulation = Simulation::new();
+        simulation.tool = Tool::Cloth;
+        simulation.build_settings.spacing = 0.25;
+        let from = simulation.world_to_screen(Vec2::new(1.0, 1.0));
+        let to = simulation.world_to_screen(Vec2::new(2.1, 1.6));
+        for input in InputState::drag(MouseButton::Left, from, to, 4) {
+            simulation.update_headless(1.0 / 240.0, input);
+        }
+        simulation.update_headless(1.0 / 240.0, InputState::at(to));
+
+        let positions = simulation.positions();
+        assert_eq!(positions.len(), 15);
+        assert_eq!(simulation.link_strains().count(), 4 * 3 + 5 * 2);
+        // The top row is pinned, so it is still where it was built
+        for (x, pos) in positions[..5].iter().enumerate() {
+            assert!(pos.distance(Vec2::new(1.0 + x as f32 * 0.25, 1.0)) < 1e-4, "{} is at {}", x, pos);
+        }
+    }
+}
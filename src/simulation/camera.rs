@@ -0,0 +1,177 @@
+use macroquad::prelude::*;
+
+use super::InputState;
+
+
+/// Maps between world positions in meters, which the simulation uses, and screen pixels.
+/// The world is drawn through it, so points can be anywhere and the view can pan and zoom.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// World position shown in the center of the screen
+    pub center: Vec2,
+    /// Screen pixels per meter at a zoom of 1, the scale of the scene
+    pub pixels_per_meter: f32,
+    pub zoom: f32,
+    /// Point that the camera stays centered on
+    pub follow: Option<usize>,
+    /// Size of the screen in pixels, taken from the input every frame
+    pub screen_size: Vec2,
+    // Screen position of the mouse in the last frame of a middle mouse drag
+    pan_from: Option<Vec2>,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            pixels_per_meter: Camera::DEFAULT_PIXELS_PER_METER,
+            zoom: 1.0,
+            follow: None,
+            screen_size: Camera::DEFAULT_SCREEN_SIZE,
+            pan_from: None,
+        }
+    }
+}
+impl Camera {
+    const MIN_ZOOM: f32 = 0.02;
+    const MAX_ZOOM: f32 = 50.0;
+    // Zoom change per wheel notch
+    const ZOOM_STEP: f32 = 1.1;
+    pub(super) const DEFAULT_PIXELS_PER_METER: f32 = 100.0;
+    // Size of the default window, until the first input says otherwise
+    pub(super) const DEFAULT_SCREEN_SIZE: Vec2 = Vec2::new(1920.0, 1080.0);
+
+    pub fn new(center: Vec2, zoom: f32) -> Self {
+        Self {
+            center,
+            zoom: zoom.clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM),
+            ..Default::default()
+        }
+    }
+
+
+    /// Screen pixels per meter
+    pub fn scale(&self) -> f32 {
+        self.pixels_per_meter * self.zoom
+    }
+
+
+    /// Length in meters that `pixels` screen pixels cover, for things that keep their size on screen
+    pub fn pixels(&self, pixels: f32) -> f32 {
+        pixels / self.scale()
+    }
+
+
+    pub fn world_to_screen(&self, pos: Vec2) -> Vec2 {
+        (pos - self.center) * self.scale() + self.screen_size * 0.5
+    }
+
+
+    pub fn screen_to_world(&self, pos: Vec2) -> Vec2 {
+        (pos - self.screen_size * 0.5) / self.scale() + self.center
+    }
+
+
+    /// Zooms by the factor, while the world position under `screen_pos` stays where it is
+    pub fn zoom_at(&mut self, screen_pos: Vec2, factor: f32) {
+        let world_pos = self.screen_to_world(screen_pos);
+        self.zoom = (self.zoom * factor).clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM);
+        self.center += world_pos - self.screen_to_world(screen_pos);
+    }
+
+
+    /// Shows the whole area from (0, 0) to bounds
+    pub fn fit(&mut self, bounds: Vec2) {
+        let scale = self.screen_size / bounds.max(Vec2::splat(f32::EPSILON));
+        self.center = bounds * 0.5;
+        self.zoom = (scale.min_element() / self.pixels_per_meter).clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM);
+    }
+
+
+    // Middle mouse drag pans, the wheel zooms towards the mouse
+    pub(super) fn handle_input(&mut self, input: &InputState) {
+        self.screen_size = input.screen_size;
+        let mouse_pos = input.mouse_position;
+        if input.is_pressed(MouseButton::Middle) && !input.mouse_over_ui {
+            self.pan_from = Some(mouse_pos);
+        }
+        if !input.is_down(MouseButton::Middle) {
+            self.pan_from = None;
+        }
+        if let Some(from) = self.pan_from {
+            if from != mouse_pos {
+                self.center -= (mouse_pos - from) / self.scale();
+                // Panning away from the followed point would just snap back
+                self.follow = None;
+            }
+            self.pan_from = Some(mouse_pos);
+        }
+
+        // The wheel reports different amounts per platform, so only its direction is used
+        if input.wheel != 0.0 && !input.mouse_over_ui {
+            self.zoom_at(mouse_pos, Camera::ZOOM_STEP.powf(input.wheel.signum()));
+        }
+    }
+
+
+    pub(super) fn to_macroquad(&self) -> Camera2D {
+        Camera2D {
+            target: self.center,
+            // The y axis points down, like in screen coordinates
+            zoom: vec2(2.0, -2.0) * self.scale() / self.screen_size,
+            ..Default::default()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::Camera;
+
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{} != {}", a, b);
+    }
+
+
+    #[test]
+    fn center_is_in_the_middle_of_the_screen() {
+        let camera = Camera::new(Vec2::new(4.0, 2.0), 2.0);
+        assert_near(camera.world_to_screen(Vec2::new(4.0, 2.0)), Camera::DEFAULT_SCREEN_SIZE * 0.5);
+        // One meter is 100 pixels at a zoom of 1
+        assert_near(camera.world_to_screen(Vec2::new(5.0, 2.0)), Camera::DEFAULT_SCREEN_SIZE * 0.5 + Vec2::new(200.0, 0.0));
+    }
+
+
+    #[test]
+    fn screen_and_world_convert_back_and_forth() {
+        let camera = Camera::new(Vec2::new(-3.0, 7.5), 0.3);
+        for pos in [Vec2::ZERO, Vec2::new(12.5, -4.0), Vec2::new(-100.0, 30.0)] {
+            assert_near(camera.screen_to_world(camera.world_to_screen(pos)), pos);
+        }
+    }
+
+
+    #[test]
+    fn zooming_keeps_the_world_under_the_mouse() {
+        let mut camera = Camera::new(Vec2::new(1.0, 1.0), 1.0);
+        let mouse = Vec2::new(300.0, 800.0);
+        let world_pos = camera.screen_to_world(mouse);
+        camera.zoom_at(mouse, 3.0);
+        assert_eq!(camera.zoom, 3.0);
+        assert_near(camera.screen_to_world(mouse), world_pos);
+    }
+
+
+    #[test]
+    fn fit_shows_the_whole_bounds() {
+        let mut camera = Camera::default();
+        camera.fit(Vec2::new(40.0, 10.0));
+        assert_near(camera.world_to_screen(Vec2::new(20.0, 5.0)), Camera::DEFAULT_SCREEN_SIZE * 0.5);
+        // The width is what limits the zoom
+        assert_near(camera.world_to_screen(Vec2::ZERO).with_y(0.0), Vec2::ZERO);
+        assert_near(camera.world_to_screen(Vec2::new(40.0, 0.0)).with_y(0.0), Vec2::new(Camera::DEFAULT_SCREEN_SIZE.x, 0.0));
+    }
+}
@@ -0,0 +1,95 @@
+use super::Link;
+
+
+// Greedy graph coloring of the links, where two links "touch" when they share a point.
+// Links inside of one batch never share a point, so a whole batch can be solved in parallel
+// while the batches themselves are solved one after another.
+pub(super) fn color_links(links: &[Link], num_points: usize) -> Vec<Vec<usize>> {
+    // Bitmask of the colors already used by the links of each point
+    let mut used_colors = vec![0u64; num_points];
+    let mut batches: Vec<Vec<usize>> = vec![];
+    // Links of points with more than 64 links get solved on their own, one after another
+    let mut overflow = vec![];
+
+    for (link_idx, link) in links.iter().enumerate() {
+        let used = used_colors[link.from_idx] | used_colors[link.to_idx];
+        let color = (!used).trailing_zeros() as usize;
+        if color >= 64 {
+            overflow.push(link_idx);
+            continue;
+        }
+        used_colors[link.from_idx] |= 1 << color;
+        used_colors[link.to_idx] |= 1 << color;
+        if batches.len() <= color {
+            batches.resize_with(color + 1, Vec::new);
+        }
+        batches[color].push(link_idx);
+    }
+
+    batches.extend(overflow.into_iter().map(|link_idx| vec![link_idx]));
+    batches
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::color_links;
+    use crate::simulation::Link;
+
+
+    // Every link is in exactly one batch, and no two links of a batch share a point
+    fn check_batches(links: &[Link], batches: &[Vec<usize>]) {
+        let mut seen = vec![false; links.len()];
+        for batch in batches.iter() {
+            let mut points = vec![];
+            for link_idx in batch.iter() {
+                assert!(!seen[*link_idx], "link {} is in two batches", link_idx);
+                seen[*link_idx] = true;
+                points.extend([links[*link_idx].from_idx, links[*link_idx].to_idx]);
+            }
+            let num_points = points.len();
+            points.sort_unstable();
+            points.dedup();
+            assert_eq!(points.len(), num_points, "batch {:?} shares points", batch);
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+
+    #[test]
+    fn chain_needs_two_batches() {
+        let links = (0..10).map(|i| Link::new(i, i + 1)).collect::<Vec<_>>();
+        let batches = color_links(&links, 11);
+        check_batches(&links, &batches);
+        assert_eq!(batches.len(), 2);
+    }
+
+
+    #[test]
+    fn grid_batches_dont_share_points() {
+        let size = 6;
+        let mut links = vec![];
+        for y in 0..size {
+            for x in 0..size {
+                let idx = y * size + x;
+                if x < size - 1 {
+                    links.push(Link::new(idx, idx + 1));
+                }
+                if y < size - 1 {
+                    links.push(Link::new(idx, idx + size));
+                }
+            }
+        }
+        check_batches(&links, &color_links(&links, size * size));
+    }
+
+
+    #[test]
+    fn links_past_64_per_point_get_their_own_batch() {
+        let links = (1..=70).map(|i| Link::new(0, i)).collect::<Vec<_>>();
+        let batches = color_links(&links, 71);
+        check_batches(&links, &batches);
+        assert_eq!(batches.len(), 70);
+        assert!(batches.iter().all(|batch| batch.len() == 1));
+    }
+}
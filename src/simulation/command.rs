@@ -0,0 +1,207 @@
+use macroquad::{color::Color, math::Vec2};
+
+use super::{grab::GrabTarget, Explosion, GuardSettings, IKChain, Link, Point, Replay, SimulationState};
+
+
+/// Identifies a link by its index. The points it connects are used to check that the index still
+/// points at the same link, since links might have been removed since the index was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkRef {
+    pub(super) idx: usize,
+    pub(super) from_idx: usize,
+    pub(super) to_idx: usize,
+}
+impl LinkRef {
+    pub(super) fn new(idx: usize, link: &Link) -> Self {
+        Self {
+            idx,
+            from_idx: link.from_idx,
+            to_idx: link.to_idx,
+        }
+    }
+
+
+    // Index of the link in `links`, which can have shifted since the reference was made
+    pub(super) fn find(&self, links: &[Link]) -> Option<usize> {
+        let matches = |link: &Link| link.from_idx == self.from_idx && link.to_idx == self.to_idx;
+        if links.get(self.idx).is_some_and(matches) {
+            return Some(self.idx);
+        }
+        links.iter().position(matches)
+    }
+}
+
+
+/// An edit of the simulation. Every edit goes through `Simulation::push_command`, which queues it until
+/// the start of the next batch of steps (or the end of the frame while paused). That way edits never
+/// land in the middle of a step, no matter if the simulation is paused, running or on its own thread.
+#[derive(Debug, Clone)]
+pub enum Command {
+    AddPoints(Vec<Point>),
+    // Removes the points and all links attached to them. Indices of the later points shift down
+    RemovePoints(Vec<usize>),
+    AddLink(Link),
+    AddIKChain(IKChain),
+    // Size of the area the points are kept inside of
+    SetBounds(Vec2),
+    // Acceleration that acts on every point
+    SetForce(Vec2),
+    SetPaused(bool),
+    // Moves a point and removes its velocity
+    MovePoint(usize, Vec2),
+    SetPointColor(usize, Color),
+    SetFixed(usize, bool),
+    SetMass(usize, f32),
+    // Sets the velocity (m/s) of the point, by moving its previous position
+    SetVelocity(usize, Vec2),
+    // Replaces lengths, stiffness, damping and break stress of a link
+    SetLinkParams(LinkRef, Link),
+    CutLinks(Vec<LinkRef>),
+    SetIKTarget(usize, Vec2),
+    // Replaces the grabbed points and the strength they are pulled with
+    SetGrab(Vec<GrabTarget>, f32),
+    ApplyImpulse(usize, Vec2),
+    Explode(Explosion),
+    // How instabilities get detected and handled, see GuardSettings
+    SetGuard(GuardSettings),
+    // Measures the energy after every step, even when the guard doesnt need it
+    SetMeasureEnergy(bool),
+    // Steps with a fixed delta (s) and without threads, or like normal with None
+    SetDeterministic(Option<f32>),
+    // Starts recording a replay with the seed, see Replay
+    StartRecording(u64),
+    // Stops the recording and sends it to the render thread
+    StopRecording,
+    // Replaces everything with the start of the replay, and plays it back
+    PlayReplay(Box<Replay>),
+}
+
+
+impl SimulationState {
+    pub(super) fn apply_command(&mut self, command: Command) {
+        if let Some(replay) = &mut self.recording {
+            if command.is_recorded() {
+                replay.record_command(self.steps, command.clone());
+            }
+        }
+        // Rolling back to a checkpoint would undo the edit. Dragged points and colors are fine to lose,
+        // the next frame of the drag moves them again, and dropping the checkpoint every frame would copy the state every frame
+        if !matches!(command, Command::SetPaused(_) | Command::SetIKTarget(..) | Command::SetGrab(..) | Command::SetGuard(_) | Command::SetMeasureEnergy(_)
+            | Command::StopRecording | Command::MovePoint(..) | Command::SetVelocity(..) | Command::SetPointColor(..)) {
+            self.guard.drop_checkpoint();
+        }
+        match command {
+            Command::AddPoints(points) => self.add_points(&points),
+            Command::RemovePoints(removed) => {
+                let removed = super::points_to_remove(removed, self.positions.len());
+                self.remove_points(&removed);
+            },
+            // Commands can be pushed without the checks of the try_ functions, and indices that were
+            // valid when they got pushed can be gone by now. Invalid links and chains are dropped
+            Command::AddLink(link) => {
+                if link.validate(self.positions.len()).is_ok() {
+                    self.add_link(link);
+                }
+            },
+            Command::AddIKChain(ik_chain) => {
+                let links = self.links.iter().map(|link| (link.from_idx, link.to_idx)).collect::<Vec<_>>();
+                if ik_chain.validate(&links).is_ok() {
+                    self.add_ik_chain(ik_chain);
+                }
+            },
+            // The walls might have moved into sleeping points, so wake everything up
+            Command::SetBounds(bounds) => {
+                self.bounds = bounds;
+                self.islands.wake_all();
+            },
+            Command::SetForce(force) => {
+                self.force = force;
+                self.islands.wake_all();
+            },
+            // Pausing is handled by whoever steps the state
+            Command::SetPaused(_) => (),
+            Command::MovePoint(point_idx, position) => {
+                if point_idx < self.positions.len() {
+                    self.positions[point_idx] = position;
+                    self.prev_positions[point_idx] = position;
+                    self.islands.wake_point(point_idx);
+                    // The guard would take the jump in energy for an instability otherwise
+                    self.guard.pushed = true;
+                }
+            },
+            Command::SetPointColor(point_idx, color) => {
+                if let Some(point_color) = self.colors.get_mut(point_idx) {
+                    *point_color = color;
+                }
+            },
+            Command::SetFixed(point_idx, fixed) => {
+                if let Some(point_fixed) = self.fixed.get_mut(point_idx) {
+                    *point_fixed = fixed;
+                    self.islands.wake_point(point_idx);
+                }
+            },
+            Command::SetMass(point_idx, mass) => {
+                if let Some(point_mass) = self.masses.get_mut(point_idx) {
+                    *point_mass = mass;
+                    self.islands.wake_point(point_idx);
+                }
+            },
+            Command::SetVelocity(point_idx, velocity) => {
+                if point_idx < self.positions.len() {
+                    self.prev_positions[point_idx] = self.positions[point_idx] - velocity * self.step_delta;
+                    self.islands.wake_point(point_idx);
+                    self.guard.pushed = true;
+                    self.guard.pushed_points.push(point_idx);
+                }
+            },
+            Command::SetLinkParams(link_ref, params) => {
+                if let Some(link_idx) = link_ref.find(&self.links) {
+                    let link = &mut self.links[link_idx];
+                    link.min_length = params.min_length.min(params.max_length);
+                    link.max_length = params.max_length;
+                    link.stiffness = params.stiffness;
+                    link.damping = params.damping;
+                    link.max_stress = params.max_stress;
+                    self.topology_events.push(super::TopologyEvent::Changed(link_idx, link.clone()));
+                    self.islands.wake_point(link.from_idx);
+                }
+            },
+            Command::CutLinks(link_refs) => {
+                let removed = super::links_to_cut(&self.links, &link_refs);
+                self.remove_links(&removed);
+            },
+            Command::SetIKTarget(chain_idx, position) => {
+                if let Some(chain) = self.ik_chains.get_mut(chain_idx) {
+                    chain.target_position = position;
+                    // The chain might jump to the new target
+                    self.guard.pushed = true;
+                }
+            },
+            Command::SetGrab(grabbed, strength) => {
+                self.grabbed = grabbed;
+                self.grab_strength = strength;
+            },
+            Command::ApplyImpulse(point_idx, impulse) => {
+                if point_idx < self.positions.len() {
+                    self.impulses.push((point_idx, impulse));
+                    self.islands.wake_point(point_idx);
+                }
+            },
+            Command::Explode(explosion) => self.explode(explosion),
+            Command::SetGuard(settings) => {
+                self.guard.settings = settings;
+                self.guard.substeps = self.guard.substeps.min(settings.max_substeps.max(1));
+            },
+            Command::SetMeasureEnergy(val) => self.measure_energy = val,
+            // Recordings and replays only work with the delta they started with
+            Command::SetDeterministic(delta) => {
+                if self.recording.is_none() && self.player.is_none() {
+                    self.fixed_delta = delta;
+                }
+            },
+            Command::StartRecording(seed) => self.start_recording(seed),
+            Command::StopRecording => self.stop_recording(),
+            Command::PlayReplay(replay) => self.play_replay(&replay),
+        }
+    }
+}
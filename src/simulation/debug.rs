@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+
+use macroquad::{prelude::*, ui::{self, hash}};
+
+use super::{query::SpatialGrid, render::{Renderer, Space}, snapshot::RenderSnapshot, Camera, Instability, RecoveryPolicy, Simulation};
+
+
+// Strain at which links get the hottest color of the strain heatmap
+const MAX_HEAT_STRAIN: f32 = 0.1;
+// Velocity arrows show where the point will be in this many seconds
+const VELOCITY_ARROW_TIME: f32 = 0.1;
+// Length (screen pixels) of the force arrows per m/s² of acceleration, and their distance from each other
+const FORCE_ARROW_SCALE: f32 = 3.0;
+const FORCE_ARROW_SPACING: f32 = 100.0;
+// Drawing text is slow, so only this many labels are drawn
+const MAX_LABELS: usize = 500;
+const HEATMAP_COLORS: [Color; 4] = [BLUE, GREEN, YELLOW, RED];
+// Opacity of the filled faces, so the links stay visible through them
+const FILL_ALPHA: f32 = 0.5;
+
+
+/// What the links get colored by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Heatmap {
+    #[default]
+    Off,
+    // How far the link is stretched or compressed past its rest lengths
+    Strain,
+    // How close the link is to its break load
+    Load,
+}
+
+
+/// Layers that get drawn on top of the simulation, to see what is going on inside of it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugOverlays {
+    /// Fills the triangles and quads that the links close, so cloth and soft bodies look solid
+    pub fill: bool,
+    pub velocities: bool,
+    pub heatmap: Heatmap,
+    pub forces: bool,
+    pub ik: bool,
+    pub labels: bool,
+    pub bounds: bool,
+    pub grid: bool,
+    /// Frame times and counts, see Simulation::profiler
+    pub profiler: bool,
+    /// Charts of the energy and momentum, see Simulation::energy
+    pub energy: bool,
+}
+
+
+// Everything besides the snapshot that the overlays need, borrowed from the simulation for one frame
+pub(super) struct DebugDraw<'a> {
+    pub(super) overlays: DebugOverlays,
+    pub(super) camera: &'a Camera,
+    pub(super) grid: &'a SpatialGrid,
+    pub(super) bounds: Vec2,
+    pub(super) force: Vec2,
+    pub(super) step_delta: f32,
+}
+impl DebugDraw<'_> {
+    // Color of the link on the heatmap, None if the heatmap is off
+    pub(super) fn link_color(&self, snapshot: &RenderSnapshot, link_idx: usize) -> Option<Color> {
+        let link = &snapshot.links[link_idx];
+        let heat = match self.overlays.heatmap {
+            Heatmap::Off => return None,
+            Heatmap::Strain => {
+                let length = snapshot.positions[link.from_idx].distance(snapshot.positions[link.to_idx]);
+                link.strain(length).abs() / MAX_HEAT_STRAIN
+            },
+            Heatmap::Load => {
+                let load = Simulation::link_offset(link, &snapshot.positions).map_or(0.0, |offset| {
+                    Simulation::link_load(offset, snapshot.masses[link.from_idx], snapshot.masses[link.to_idx], self.step_delta)
+                });
+                load / link.max_stress
+            },
+        };
+        Some(heat_color(heat))
+    }
+
+
+    // Drawn below the links and points
+    pub(super) fn draw_background(&self, snapshot: &RenderSnapshot, renderer: &mut impl Renderer) {
+        let pixel = self.camera.pixels(1.0);
+        if self.overlays.fill {
+            self.draw_fill(snapshot, renderer);
+        }
+        if self.overlays.grid {
+            for (corner, size, points, links) in self.grid.occupied_cells() {
+                let fill = ((points + links) as f32 / 16.0).min(1.0) * 0.25;
+                renderer.rect(corner, Vec2::splat(size), Color::new(0.0, 0.6, 1.0, fill));
+                renderer.rect_lines(corner, Vec2::splat(size), pixel, Color::new(0.0, 0.6, 1.0, 0.4));
+            }
+        }
+        if self.overlays.bounds {
+            renderer.rect_lines(Vec2::ZERO, self.bounds, 2.0 * pixel, GRAY);
+        }
+        if self.overlays.forces {
+            self.draw_force_field(renderer);
+        }
+        if self.overlays.ik {
+            for chain in snapshot.ik_chains.iter() {
+                let (Some(first), Some(last)) = (chain.links.first(), chain.links.last()) else {
+                    continue;
+                };
+                let root = snapshot.positions[snapshot.links[*first].from_idx];
+                renderer.circle_lines(root, chain.current_max_length, pixel, Color::new(1.0, 0.0, 1.0, 0.5));
+                let end = snapshot.positions[snapshot.links[*last].to_idx];
+                let target = chain.target_position;
+                renderer.line(end, target, pixel, MAGENTA);
+                let size = 6.0 * pixel;
+                renderer.line(target - size, target + size, 2.0 * pixel, MAGENTA);
+                renderer.line(target + Vec2::new(-size, size), target + Vec2::new(size, -size), 2.0 * pixel, MAGENTA);
+            }
+        }
+    }
+
+
+    // Drawn on top of the links and points
+    pub(super) fn draw_foreground(&self, snapshot: &RenderSnapshot, renderer: &mut impl Renderer) {
+        if self.overlays.velocities {
+            let delta = self.step_delta.max(f32::EPSILON);
+            for i in 0..snapshot.positions.len() {
+                let velocity = (snapshot.positions[i] - snapshot.prev_positions[i]) / delta;
+                let pos = snapshot.positions[i];
+                if !snapshot.fixed[i] {
+                    self.draw_arrow(renderer, pos, pos + velocity * VELOCITY_ARROW_TIME, SKYBLUE);
+                }
+            }
+        }
+
+        // Text and the legend are drawn in screen pixels
+        renderer.set_space(Space::Screen);
+        if self.overlays.labels {
+            self.draw_labels(snapshot, renderer);
+        }
+        self.draw_legend(renderer);
+        renderer.set_space(Space::World);
+    }
+
+
+    // Every face in the colors of its corners
+    fn draw_fill(&self, snapshot: &RenderSnapshot, renderer: &mut impl Renderer) {
+        for face in faces(snapshot) {
+            let color = face.iter().fold(Vec4::ZERO, |sum, idx| sum + snapshot.colors[*idx].to_vec()) / face.len() as f32;
+            let points = face.iter().map(|idx| snapshot.positions[*idx]).collect::<Vec<_>>();
+            renderer.polygon(&points, Color::new(color.x, color.y, color.z, color.w * FILL_ALPHA));
+        }
+    }
+
+
+    // Arrows on a regular grid over the screen, the acceleration is the same everywhere
+    fn draw_force_field(&self, renderer: &mut impl Renderer) {
+        let spacing = self.camera.pixels(FORCE_ARROW_SPACING);
+        let length = self.camera.pixels(FORCE_ARROW_SCALE);
+        let min = (self.camera.screen_to_world(Vec2::ZERO) / spacing).floor() * spacing;
+        let max = self.camera.screen_to_world(self.camera.screen_size);
+        let mut y = min.y;
+        while y < max.y {
+            let mut x = min.x;
+            while x < max.x {
+                let pos = Vec2::new(x, y) + spacing * 0.5;
+                self.draw_arrow(renderer, pos, pos + self.force * length, Color::new(1.0, 0.6, 0.0, 0.5));
+                x += spacing;
+            }
+            y += spacing;
+        }
+    }
+
+
+    fn draw_labels(&self, snapshot: &RenderSnapshot, renderer: &mut impl Renderer) {
+        let screen = self.camera.screen_size;
+        let on_screen = |pos: Vec2| pos.cmpge(Vec2::ZERO).all() && pos.cmple(screen).all();
+        let points = (0..snapshot.positions.len())
+            .map(|i| (i, self.camera.world_to_screen(snapshot.positions[i])))
+            .filter(|(_, pos)| on_screen(*pos));
+        for (i, pos) in points.take(MAX_LABELS) {
+            renderer.text(&i.to_string(), pos + Vec2::new(8.0, -8.0), 16.0, WHITE);
+        }
+        let links = snapshot.links.iter().enumerate()
+            .map(|(i, link)| (i, self.camera.world_to_screen((snapshot.positions[link.from_idx] + snapshot.positions[link.to_idx]) * 0.5)))
+            .filter(|(_, pos)| on_screen(*pos));
+        for (i, pos) in links.take(MAX_LABELS) {
+            renderer.text(&i.to_string(), pos + Vec2::new(0.0, 14.0), 14.0, LIGHTGRAY);
+        }
+    }
+
+
+    // Explains the colors and arrows, in the bottom left corner
+    fn draw_legend(&self, renderer: &mut impl Renderer) {
+        let mut y = self.camera.screen_size.y - 20.0;
+        let mut line = |text: &str, color: Color| {
+            renderer.text(text, Vec2::new(20.0, y), 18.0, color);
+            y -= 22.0;
+        };
+        if self.overlays.velocities {
+            line(&format!("Velocity arrows: motion in the next {} s", VELOCITY_ARROW_TIME), SKYBLUE);
+        }
+        if self.overlays.forces {
+            line(&format!("Force field: {:.2} m/s²", self.force.length()), ORANGE);
+        }
+        if self.overlays.ik {
+            line("IK: target and reach of the chains", MAGENTA);
+        }
+        let (title, max) = match self.overlays.heatmap {
+            Heatmap::Off => return,
+            Heatmap::Strain => ("Strain", format!("{}%", MAX_HEAT_STRAIN * 100.0)),
+            Heatmap::Load => ("Load of break load", "100%".to_owned()),
+        };
+        // Gradient bar with the range below it
+        let (width, height) = (200.0, 12.0);
+        renderer.text("0%", Vec2::new(20.0, y), 16.0, WHITE);
+        renderer.text(&max, Vec2::new(20.0 + width - renderer.text_width(&max, 16.0), y), 16.0, WHITE);
+        let bar_top = y - 16.0 - height;
+        let steps = 40;
+        for i in 0..steps {
+            let t = i as f32 / steps as f32;
+            renderer.rect(Vec2::new(20.0 + t * width, bar_top), Vec2::new(width / steps as f32 + 0.5, height), heat_color(t));
+        }
+        renderer.text(title, Vec2::new(20.0, bar_top - 6.0), 18.0, WHITE);
+    }
+
+
+    // Arrow from start to end (world units), with a head that keeps its size on screen
+    fn draw_arrow(&self, renderer: &mut impl Renderer, start: Vec2, end: Vec2, color: Color) {
+        let pixel = self.camera.pixels(1.0);
+        let dir = end - start;
+        if dir.length() < 2.0 * pixel {
+            return;
+        }
+        renderer.line(start, end, pixel, color);
+        let head = dir.normalize() * 6.0 * pixel;
+        for side in [head.perp(), -head.perp()] {
+            let corner = end - head + side * 0.5;
+            renderer.line(end, corner, pixel, color);
+        }
+    }
+}
+
+
+// Triangles of three linked points, and quads of four linked points in a ring without diagonals.
+// Quads with a diagonal are two triangles already
+fn faces(snapshot: &RenderSnapshot) -> Vec<Vec<usize>> {
+    let mut neighbors = vec![vec![]; snapshot.positions.len()];
+    for link in snapshot.links.iter() {
+        neighbors[link.from_idx].push(link.to_idx);
+        neighbors[link.to_idx].push(link.from_idx);
+    }
+    let linked = |a: usize, b: usize| neighbors[a].contains(&b);
+    let mut faces = vec![];
+    let mut quads = HashSet::new();
+    for a in 0..neighbors.len() {
+        for &b in neighbors[a].iter().filter(|b| **b > a) {
+            // Each triangle once, from its two lowest indices
+            for &c in neighbors[b].iter().filter(|c| **c > b) {
+                if linked(a, c) {
+                    faces.push(vec![a, b, c]);
+                }
+            }
+            for &c in neighbors[b].iter().filter(|c| **c != a && !linked(a, **c)) {
+                for &d in neighbors[c].iter().filter(|d| **d != b && **d != a && linked(a, **d) && !linked(b, **d)) {
+                    let mut key = [a, b, c, d];
+                    key.sort_unstable();
+                    if quads.insert(key) {
+                        faces.push(vec![a, b, c, d]);
+                    }
+                }
+            }
+        }
+    }
+    faces
+}
+
+
+// Blue when cold (0), red when hot (1)
+fn heat_color(heat: f32) -> Color {
+    let t = heat.clamp(0.0, 1.0) * (HEATMAP_COLORS.len() - 1) as f32;
+    let i = (t as usize).min(HEATMAP_COLORS.len() - 2);
+    Color::from_vec(HEATMAP_COLORS[i].to_vec().lerp(HEATMAP_COLORS[i + 1].to_vec(), t - i as f32))
+}
+
+
+impl Simulation {
+    pub(super) fn draw_debug_window(&mut self) {
+        let overlays = &mut self.debug;
+        let guard = &mut self.guard;
+        let step_delta = self.snapshot.step_delta;
+        let last_instability = &self.last_instability;
+        ui::widgets::Window::new(hash!(), vec2(screen_width() - 260.0, 420.0), vec2(250.0, 362.0))
+            .label("Debug")
+            .movable(false)
+            .ui(&mut ui::root_ui(), |ui| {
+                let mut heatmap_idx = overlays.heatmap as usize;
+                ui.combo_box(hash!(), "Links", &["Plain", "Strain", "Load"], &mut heatmap_idx);
+                overlays.heatmap = [Heatmap::Off, Heatmap::Strain, Heatmap::Load][heatmap_idx];
+                ui.checkbox(hash!(), "Fill bodies", &mut overlays.fill);
+                ui.checkbox(hash!(), "Velocities", &mut overlays.velocities);
+                ui.checkbox(hash!(), "Force field", &mut overlays.forces);
+                ui.checkbox(hash!(), "IK targets", &mut overlays.ik);
+                ui.checkbox(hash!(), "Indices", &mut overlays.labels);
+                ui.checkbox(hash!(), "World bounds", &mut overlays.bounds);
+                ui.checkbox(hash!(), "Broadphase grid", &mut overlays.grid);
+                ui.checkbox(hash!(), "Profiler", &mut overlays.profiler);
+                ui.checkbox(hash!(), "Energy charts", &mut overlays.energy);
+
+                ui.separator();
+                let policies = [RecoveryPolicy::Rollback, RecoveryPolicy::Clamp, RecoveryPolicy::Report];
+                let mut policy_idx = policies.iter().position(|policy| *policy == guard.policy).unwrap_or(0);
+                ui.combo_box(hash!(), "Instability", &["Roll back", "Clamp", "Report"], &mut policy_idx);
+                guard.policy = policies[policy_idx];
+                ui.checkbox(hash!(), "Adaptive substeps", &mut guard.adaptive_substeps);
+                ui.label(None, &format!("Substep: {:.2} ms", step_delta * 1000.0));
+                if let Some(event) = last_instability {
+                    let kind = match event.instability {
+                        Instability::NonFinite => "NaN".to_owned(),
+                        Instability::Speed(speed) => format!("{:.0} m/s", speed),
+                        Instability::Energy(before, after) => format!("{:.1} -> {:.1} J", before, after),
+                    };
+                    ui.label(None, &format!("Last: {} at {:.1} s, {:?}", kind, event.time, event.response));
+                }
+        });
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use super::{Simulation, SimulationState};
+
+
+// Simulated time (s) the charts and the growth check go back
+const HISTORY_DURATION: f32 = 5.0;
+// The total energy of the last second has to be this much larger than the second before it to count as growing
+const GROWTH_WARNING: f32 = 0.05;
+// Energies below this (J) are too small to warn about, they are mostly noise
+const MIN_WARNING_ENERGY: f32 = 0.01;
+const CHART_SIZE: Vec2 = Vec2::new(400.0, 100.0);
+
+
+// Picks one of the values out of a sample, for the charts
+type SampleValue = fn(&EnergySample) -> f32;
+
+
+/// Energies (J) and momentum (kg*m/s) of all points and links after one step
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnergySample {
+    /// Simulated time (s) of the step
+    pub time: f32,
+    pub kinetic: f32,
+    /// Relative to the wall of the bounds that the force points towards, so it is never negative inside of them
+    pub potential: f32,
+    /// Stored in the links that are stretched or compressed past their rest lengths
+    pub elastic: f32,
+    pub momentum: Vec2,
+}
+impl EnergySample {
+    pub fn total(&self) -> f32 {
+        self.kinetic + self.potential + self.elastic
+    }
+
+
+    pub(super) fn measure(state: &SimulationState, delta: f32) -> Self {
+        let delta = delta.max(f32::EPSILON);
+        // The "floor" of the potential energy
+        let reference = Vec2::new(
+            if state.force.x > 0.0 { state.bounds.x } else { 0.0 },
+            if state.force.y > 0.0 { state.bounds.y } else { 0.0 },
+        );
+        let mut sample = EnergySample { time: state.time, ..Default::default() };
+        for i in 0..state.positions.len() {
+            if state.fixed[i] {
+                continue;
+            }
+            let mass = state.masses[i];
+            let velocity = (state.positions[i] - state.prev_positions[i]) / delta;
+            sample.kinetic += 0.5 * mass * velocity.length_squared();
+            sample.potential += mass * state.force.dot(reference - state.positions[i]);
+            sample.momentum += mass * velocity;
+        }
+        // The links act like springs whose force is their load, which stores load * deviation / 2
+        for link in state.links.iter() {
+            let Some(offset) = Simulation::link_offset(link, &state.positions) else {
+                continue;
+            };
+            let length = state.positions[link.from_idx].distance(state.positions[link.to_idx]);
+            let deviation = (length - length.clamp(link.min_length, link.max_length)).abs();
+            let load = Simulation::link_load(offset, state.masses[link.from_idx], state.masses[link.to_idx], delta);
+            sample.elastic += 0.5 * load * deviation;
+        }
+        sample
+    }
+}
+
+
+/// Keeps the energy of the last steps, to chart it and to notice when it keeps growing
+#[derive(Debug, Default)]
+pub struct EnergyMonitor {
+    history: VecDeque<EnergySample>,
+}
+impl EnergyMonitor {
+    pub(super) fn extend(&mut self, samples: impl IntoIterator<Item = EnergySample>) {
+        for sample in samples {
+            // Rollbacks and replays send the time backwards, the samples after it didnt happen anymore
+            while self.history.back().is_some_and(|back| back.time > sample.time) {
+                self.history.pop_back();
+            }
+            while self.history.front().is_some_and(|front| front.time < sample.time - HISTORY_DURATION) {
+                self.history.pop_front();
+            }
+            self.history.push_back(sample);
+        }
+    }
+
+
+    /// Energy after the last step
+    pub fn last(&self) -> Option<&EnergySample> {
+        self.history.back()
+    }
+
+
+    /// Energy of the last steps, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &EnergySample> {
+        self.history.iter()
+    }
+
+
+    /// Relative growth of the average total energy of the last second compared to the second before it.
+    /// None if there isnt enough history yet
+    pub fn growth(&self) -> Option<f32> {
+        let end = self.history.back()?.time;
+        let average = |from: f32, to: f32| {
+            let (sum, count) = self.history.iter()
+                .filter(|sample| sample.time > from && sample.time <= to)
+                .fold((0.0, 0), |(sum, count), sample| (sum + sample.total(), count + 1));
+            (count > 0).then(|| sum / count as f32)
+        };
+        if self.history.front()?.time > end - 2.0 {
+            return None;
+        }
+        let previous = average(end - 2.0, end - 1.0)?;
+        let current = average(end - 1.0, end)?;
+        if previous.max(current) < MIN_WARNING_ENERGY {
+            return Some(0.0);
+        }
+        Some((current - previous) / previous.max(MIN_WARNING_ENERGY))
+    }
+
+
+    /// Whether the energy grew noticeably in the last second, which usually means the scene is unstable.
+    /// Grabbing and explosions add energy too, so this only means something while nobody interacts
+    pub fn is_growing(&self) -> bool {
+        self.growth().is_some_and(|growth| growth > GROWTH_WARNING)
+    }
+
+
+    // Scrolling charts of the energies and the momentum, at the bottom center of the screen
+    pub(super) fn draw_charts(&self) {
+        let left = (screen_width() - CHART_SIZE.x) * 0.5;
+        let momentum_top = screen_height() - 10.0 - CHART_SIZE.y * 0.5;
+        let energy_top = momentum_top - 30.0 - CHART_SIZE.y;
+        let Some(last) = self.last() else {
+            return;
+        };
+
+        let energies: [(&str, SampleValue, Color); 4] = [
+            ("Kinetic", |sample| sample.kinetic, SKYBLUE),
+            ("Potential", |sample| sample.potential, GREEN),
+            ("Elastic", |sample| sample.elastic, ORANGE),
+            ("Total", |sample| sample.total(), WHITE),
+        ];
+        let max_energy = self.history.iter().map(|sample| sample.total()).fold(MIN_WARNING_ENERGY, f32::max);
+        draw_rectangle(left, energy_top, CHART_SIZE.x, CHART_SIZE.y, Color::new(0.0, 0.0, 0.0, 0.6));
+        for (i, (name, value, color)) in energies.iter().enumerate() {
+            self.draw_line_chart(Vec2::new(left, energy_top), CHART_SIZE, max_energy, *value, *color);
+            // The values are listed right of the chart
+            draw_text(format!("{}: {:.2} J", name, value(last)), left + CHART_SIZE.x + 8.0, energy_top + 16.0 * (i + 1) as f32, 16.0, *color);
+        }
+        if let Some(growth) = self.growth().filter(|_| self.is_growing()) {
+            draw_text(format!("Energy is growing: +{:.0}% in the last second", growth * 100.0), left, energy_top - 6.0, 20.0, RED);
+        }
+
+        let momentum_size = Vec2::new(CHART_SIZE.x, CHART_SIZE.y * 0.5);
+        let max_momentum = self.history.iter().map(|sample| sample.momentum.length()).fold(f32::EPSILON, f32::max);
+        draw_rectangle(left, momentum_top, momentum_size.x, momentum_size.y, Color::new(0.0, 0.0, 0.0, 0.6));
+        self.draw_line_chart(Vec2::new(left, momentum_top), momentum_size, max_momentum, |sample| sample.momentum.length(), YELLOW);
+        let text = format!("Momentum: ({:.2}, {:.2}) kg*m/s", last.momentum.x, last.momentum.y);
+        draw_text(&text, left + 6.0, momentum_top - 6.0, 16.0, YELLOW);
+    }
+
+
+    // Line of the value over the history, scaled so max reaches the top
+    fn draw_line_chart(&self, top_left: Vec2, size: Vec2, max: f32, value: impl Fn(&EnergySample) -> f32, color: Color) {
+        let Some(end) = self.history.back().map(|sample| sample.time) else {
+            return;
+        };
+        let point = |sample: &EnergySample| {
+            let x = 1.0 - (end - sample.time) / HISTORY_DURATION;
+            let y = (value(sample) / max).clamp(0.0, 1.0);
+            top_left + Vec2::new(x * size.x, (1.0 - y) * size.y)
+        };
+        let mut prev: Option<Vec2> = None;
+        for sample in self.history.iter() {
+            let pos = point(sample);
+            // Every sample would be more lines than pixels
+            if prev.is_some_and(|prev| pos.x - prev.x < 1.0) {
+                continue;
+            }
+            if let Some(prev) = prev {
+                draw_line(prev.x, prev.y, pos.x, pos.y, 1.0, color);
+            }
+            prev = Some(pos);
+        }
+    }
+}
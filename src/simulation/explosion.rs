@@ -0,0 +1,96 @@
+use macroquad::math::Vec2;
+
+
+// How the strength of an explosion decreases towards its radius
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Quadratic,
+}
+impl Falloff {
+    // `t` is the distance from the center divided by the radius
+    fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::Quadratic => (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+
+/// An impulse that hits every point within a radius.
+/// Radial explosions push points away from the center, directional blasts push everything in the same direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Explosion {
+    pub(super) center: Vec2,
+    pub(super) radius: f32,
+    // Impulse (kg*m/s) at the center of the explosion
+    pub(super) strength: f32,
+    pub(super) falloff: Falloff,
+    pub(super) direction: Option<Vec2>,
+    // Links that the explosion loads with more than this (N) break
+    pub(super) break_threshold: Option<f32>,
+}
+impl Explosion {
+    pub fn new(center: Vec2, radius: f32, strength: f32) -> Self {
+        Self {
+            center,
+            radius,
+            strength,
+            falloff: Falloff::Linear,
+            direction: None,
+            break_threshold: None,
+        }
+    }
+
+    pub fn center(mut self, val: Vec2) -> Self {
+        self.center = val;
+        self
+    }
+    pub fn radius(mut self, val: f32) -> Self {
+        self.radius = val;
+        self
+    }
+    pub fn strength(mut self, val: f32) -> Self {
+        self.strength = val;
+        self
+    }
+    pub fn falloff(mut self, val: Falloff) -> Self {
+        self.falloff = val;
+        self
+    }
+    /// Turns the explosion into a directional blast
+    pub fn direction(mut self, val: Vec2) -> Self {
+        self.direction = Some(val.normalize_or_zero());
+        self
+    }
+    pub fn radial(mut self) -> Self {
+        self.direction = None;
+        self
+    }
+    /// Breaks every link that the explosion loads with more than `val` (N). The load is the force it would take
+    /// to stop the points of the link from being pushed apart or together, like the max stress of links
+    pub fn break_links(mut self, val: f32) -> Self {
+        self.break_threshold = Some(val);
+        self
+    }
+
+
+    /// Strength of the explosion at the given position, 0 outside of the radius
+    pub fn strength_at(&self, pos: Vec2) -> f32 {
+        let dist = pos.distance(self.center);
+        if dist > self.radius {
+            return 0.0;
+        }
+        self.strength * self.falloff.apply(dist / self.radius.max(f32::EPSILON))
+    }
+
+    /// The impulse a point at the given position receives
+    pub fn impulse_at(&self, pos: Vec2) -> Vec2 {
+        let dir = self.direction.unwrap_or_else(|| (pos - self.center).normalize_or_zero());
+        dir * self.strength_at(pos)
+    }
+}
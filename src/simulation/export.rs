@@ -0,0 +1,63 @@
+use std::{fs::{self, File}, io::BufWriter, path::{Path, PathBuf}};
+
+use image::{codecs::gif::{GifEncoder, Repeat}, ColorType, Delay, Frame, ImageResult, RgbaImage};
+use macroquad::texture::Image;
+
+
+/// Where exported frames go. A path ending in .gif becomes one looping animation,
+/// any other path is a directory with a numbered PNG file per frame
+pub enum FrameSink {
+    Png {
+        dir: PathBuf,
+        frames: usize,
+    },
+    Gif {
+        encoder: GifEncoder<BufWriter<File>>,
+        fps: f32,
+        frames: usize,
+    },
+}
+impl FrameSink {
+    /// Creates the file or directory, frames are shown `fps` times per second
+    pub fn create(path: impl AsRef<Path>, fps: f32) -> ImageResult<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gif")) {
+            let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+            encoder.set_repeat(Repeat::Infinite)?;
+            Ok(FrameSink::Gif { encoder, fps, frames: 0 })
+        } else {
+            fs::create_dir_all(path)?;
+            Ok(FrameSink::Png { dir: path.to_owned(), frames: 0 })
+        }
+    }
+
+
+    /// Adds the next frame. The image has to be as big as the first one
+    pub fn push(&mut self, image: &Image) -> ImageResult<()> {
+        match self {
+            FrameSink::Png { dir, frames } => {
+                let path = dir.join(format!("frame_{:05}.png", frames));
+                image::save_buffer(path, &image.bytes, image.width as u32, image.height as u32, ColorType::Rgba8)?;
+                *frames += 1;
+            },
+            FrameSink::Gif { encoder, fps, frames } => {
+                // GIFs count in hundredths of a second, the delays alternate so the total time stays right
+                let centis = |frame: usize| (frame as f32 * 100.0 / *fps).round() as u32;
+                let delay = Delay::from_numer_denom_ms((centis(*frames + 1) - centis(*frames)) * 10, 1);
+                let buffer = RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.clone())
+                    .expect("the image has four bytes per pixel");
+                encoder.encode_frame(Frame::from_parts(buffer, 0, 0, delay))?;
+                *frames += 1;
+            },
+        }
+        Ok(())
+    }
+
+
+    /// How many frames were added
+    pub fn frames(&self) -> usize {
+        match self {
+            FrameSink::Png { frames, .. } | FrameSink::Gif { frames, .. } => *frames,
+        }
+    }
+}
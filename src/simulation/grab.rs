@@ -0,0 +1,91 @@
+use macroquad::math::Vec2;
+
+use super::{query::SpatialGrid, snapshot::RenderSnapshot};
+
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GrabMode {
+    // Grab the point (or link) closest to the mouse
+    Nearest,
+    // Grab every point inside of the grab radius
+    Radius,
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct GrabSettings {
+    // How much of the distance to the mouse gets corrected each step (0..1)
+    pub strength: f32,
+    // Distance (m) from the mouse that points get grabbed from
+    pub radius: f32,
+    pub mode: GrabMode,
+}
+impl Default for GrabSettings {
+    fn default() -> Self {
+        Self {
+            strength: 0.2,
+            radius: 0.5,
+            mode: GrabMode::Nearest,
+        }
+    }
+}
+
+
+// A point being pulled towards the mouse by a spring
+#[derive(Debug, Clone, Copy)]
+pub struct GrabTarget {
+    pub(super) point_idx: usize,
+    // Offset of the point from the mouse when it got grabbed, so grabbing keeps the shape intact
+    pub(super) offset: Vec2,
+    pub(super) weight: f32,
+    pub(super) target: Vec2,
+}
+impl GrabTarget {
+    pub(super) fn new(point_idx: usize, offset: Vec2, weight: f32) -> Self {
+        Self {
+            point_idx,
+            offset,
+            weight,
+            target: Vec2::ZERO,
+        }
+    }
+
+    pub(super) fn follow(&mut self, mouse_pos: Vec2) {
+        self.target = mouse_pos + self.offset;
+    }
+}
+
+
+pub(super) fn find_grab_targets(snapshot: &RenderSnapshot, grid: &SpatialGrid, mouse_pos: Vec2, settings: &GrabSettings) -> Vec<GrabTarget> {
+    let mut targets = vec![];
+    if settings.mode == GrabMode::Radius {
+        for i in grid.points_in_radius(&snapshot.positions, mouse_pos, settings.radius) {
+            if !snapshot.fixed[i] {
+                targets.push(GrabTarget::new(i, snapshot.positions[i] - mouse_pos, 1.0));
+            }
+        }
+        return targets;
+    }
+
+    let nearest_point = grid.nearest_point(&snapshot.positions, mouse_pos, settings.radius, |i| !snapshot.fixed[i]);
+    if let Some(i) = nearest_point {
+        targets.push(GrabTarget::new(i, snapshot.positions[i] - mouse_pos, 1.0));
+        return targets;
+    }
+
+    // No point close enough, try to grab a link at the position along it that was clicked
+    if let Some(link_idx) = grid.nearest_link(snapshot, mouse_pos, settings.radius) {
+        let link = &snapshot.links[link_idx];
+        let from = snapshot.positions[link.from_idx];
+        let to = snapshot.positions[link.to_idx];
+        let ba = to - from;
+        let h = ((mouse_pos - from).dot(ba) / ba.dot(ba).max(f32::EPSILON)).clamp(0.0, 1.0);
+        if !snapshot.fixed[link.from_idx] {
+            targets.push(GrabTarget::new(link.from_idx, from - mouse_pos, 1.0 - h));
+        }
+        if !snapshot.fixed[link.to_idx] {
+            targets.push(GrabTarget::new(link.to_idx, to - mouse_pos, h));
+        }
+    }
+    targets
+}
@@ -0,0 +1,318 @@
+use macroquad::math::Vec2;
+
+use super::{energy::EnergySample, profiler::StepTimings, replay::{ReplayEvent, ReplayPlayer}, LinkBreak, Replay, Simulation, SimulationState, TopologyEvent};
+
+
+// Steps between the checkpoints that get rolled back to, as long as the simulation is stable
+const CHECKPOINT_INTERVAL: usize = 30;
+// With adaptive substeps, the substeps double when a link is strained more than HIGH_STRAIN,
+// and halve again after RELAX_STEPS steps below LOW_STRAIN
+const HIGH_STRAIN: f32 = 0.05;
+const LOW_STRAIN: f32 = 0.01;
+const RELAX_STEPS: usize = 240;
+// The energy check ignores changes below this (J)
+const MIN_ENERGY: f32 = 1.0;
+
+
+/// What the guard does once the simulation became unstable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecoveryPolicy {
+    /// Go back to the last stable checkpoint and double the substeps.
+    /// Clamps instead when there is no checkpoint, because something got edited since it was taken,
+    /// or when the substeps are at max_substeps already, since going back would only repeat the same steps
+    #[default]
+    Rollback,
+    /// Put the offending points back into the bounds and remove their velocity
+    Clamp,
+    /// Only report it, see Simulation::instabilities
+    Report,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuardSettings {
+    pub policy: RecoveryPolicy,
+    /// Points faster than this (m/s) count as exploding
+    pub max_speed: f32,
+    /// The total energy can grow at most by this factor in one step, unless something pushed the points.
+    /// Infinity turns the check off, which also saves measuring the energy after every step
+    pub max_energy_growth: f32,
+    /// Raise the substeps while links are strained a lot, and lower them again once they arent
+    pub adaptive_substeps: bool,
+    pub max_substeps: usize,
+}
+impl Default for GuardSettings {
+    fn default() -> Self {
+        Self {
+            policy: RecoveryPolicy::Rollback,
+            max_speed: 2.0 * Simulation::MAX_VELOCITY,
+            max_energy_growth: 2.0,
+            adaptive_substeps: false,
+            max_substeps: 16,
+        }
+    }
+}
+
+
+/// What went wrong
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instability {
+    /// Positions that are NaN or infinite
+    NonFinite,
+    /// The fastest point, in m/s
+    Speed(f32),
+    /// Total energy before and after the step, in J
+    Energy(f32, f32),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstabilityEvent {
+    /// Simulated time (s) of the step that went wrong
+    pub time: f32,
+    pub instability: Instability,
+    /// The offending points
+    pub points: Vec<usize>,
+    /// What was done about it
+    pub response: RecoveryPolicy,
+    /// Substeps per step after the response
+    pub substeps: usize,
+}
+
+
+// Parts of the state that the checkpoints dont copy, and that keep going when the state gets rolled back
+struct Kept {
+    guard: Guard,
+    topology_events: Vec<TopologyEvent>,
+    energy_samples: Vec<EnergySample>,
+    measure_energy: bool,
+    link_breaks: Vec<LinkBreak>,
+    timings: StepTimings,
+    steps: u64,
+    recording: Option<Box<Replay>>,
+    player: Option<Box<ReplayPlayer>>,
+    replay_events: Vec<ReplayEvent>,
+}
+
+
+// Watches the state after every step, see SimulationState::check_stability
+#[derive(Debug, Clone)]
+pub(super) struct Guard {
+    pub(super) settings: GuardSettings,
+    // Every step is split into this many substeps
+    pub(super) substeps: usize,
+    // Copy of the state from a stable step, with an empty guard
+    checkpoint: Option<Box<SimulationState>>,
+    // Stable steps since the last checkpoint, and since the strain was last high
+    steps_since_checkpoint: usize,
+    relaxed_steps: usize,
+    last_energy: Option<f32>,
+    // Something pushed the points on purpose since the last check, so the energy is allowed to grow.
+    // The pushed points themselves are also allowed to be fast
+    pub(super) pushed: bool,
+    pub(super) pushed_points: Vec<usize>,
+    // Found since the last render snapshot
+    pub(super) events: Vec<InstabilityEvent>,
+}
+impl Default for Guard {
+    fn default() -> Self {
+        Self {
+            settings: GuardSettings::default(),
+            substeps: 1,
+            checkpoint: None,
+            steps_since_checkpoint: 0,
+            relaxed_steps: 0,
+            last_energy: None,
+            pushed: false,
+            pushed_points: vec![],
+            events: vec![],
+        }
+    }
+}
+impl Guard {
+    // Edits would get undone by rolling back to a checkpoint from before them
+    pub(super) fn drop_checkpoint(&mut self) {
+        self.checkpoint = None;
+        self.steps_since_checkpoint = 0;
+        self.last_energy = None;
+    }
+}
+
+
+impl SimulationState {
+    // Looks for NaNs, exploding velocities and exploding energy after a step, and responds as the settings say.
+    // Otherwise takes checkpoints and adapts the substeps
+    pub(super) fn check_stability(&mut self) {
+        let settings = self.guard.settings;
+        let delta = self.step_delta.max(f32::EPSILON);
+        let non_finite = (0..self.positions.len())
+            .filter(|i| !self.positions[*i].is_finite() || !self.prev_positions[*i].is_finite())
+            .collect::<Vec<_>>();
+        let mut pushed_points = std::mem::take(&mut self.guard.pushed_points);
+        pushed_points.extend(self.grabbed.iter().map(|target| target.point_idx));
+        pushed_points.extend(self.kinematic_paths.iter().map(|(idx, _)| *idx));
+        let links = &self.links;
+        pushed_points.extend(self.ik_chains.iter().flat_map(|chain| chain.links.iter().flat_map(|link_idx| [links[*link_idx].from_idx, links[*link_idx].to_idx])));
+        pushed_points.sort_unstable();
+        let pushed = std::mem::take(&mut self.guard.pushed) || !pushed_points.is_empty();
+        let speeds = (0..self.positions.len())
+            .filter(|i| !self.fixed[*i] && pushed_points.binary_search(i).is_err())
+            .map(|i| (i, self.positions[i].distance(self.prev_positions[i]) / delta));
+        let too_fast = speeds.clone().filter(|(_, speed)| *speed > settings.max_speed).map(|(i, _)| i).collect::<Vec<_>>();
+        let energy = self.energy_samples.last().map(|sample| sample.total());
+
+        let instability = if !non_finite.is_empty() {
+            Some((Instability::NonFinite, non_finite))
+        } else if !too_fast.is_empty() {
+            let max = speeds.map(|(_, speed)| speed).fold(0.0, f32::max);
+            Some((Instability::Speed(max), too_fast))
+        } else {
+            match (self.guard.last_energy, energy) {
+                (Some(before), Some(after)) if !pushed && after > MIN_ENERGY && after > before.max(MIN_ENERGY) * settings.max_energy_growth => {
+                    // Blame the points that move faster than the integration allows, or everything if there are none
+                    let moving = (0..self.positions.len()).filter(|i| !self.fixed[*i]);
+                    let mut fast = moving.clone()
+                        .filter(|i| self.positions[*i].distance(self.prev_positions[*i]) / delta > Simulation::MAX_VELOCITY)
+                        .collect::<Vec<_>>();
+                    if fast.is_empty() {
+                        fast = moving.collect();
+                    }
+                    Some((Instability::Energy(before, after), fast))
+                },
+                _ => None,
+            }
+        };
+
+        let Some((instability, points)) = instability else {
+            self.guard.last_energy = energy;
+            self.on_stable_step();
+            return;
+        };
+        let time = self.time;
+        let response = match settings.policy {
+            RecoveryPolicy::Rollback if self.rollback() => RecoveryPolicy::Rollback,
+            RecoveryPolicy::Rollback | RecoveryPolicy::Clamp => {
+                self.clamp_points(&points);
+                RecoveryPolicy::Clamp
+            },
+            RecoveryPolicy::Report => RecoveryPolicy::Report,
+        };
+        self.guard.last_energy = None;
+        let event = InstabilityEvent { time, instability, points, response, substeps: self.guard.substeps };
+        self.guard.events.push(event);
+    }
+
+
+    fn on_stable_step(&mut self) {
+        self.guard.steps_since_checkpoint += 1;
+        // Only rolling back reads the checkpoints, and copying the state isnt cheap
+        if self.guard.settings.policy != RecoveryPolicy::Rollback {
+            self.guard.checkpoint = None;
+        } else if self.guard.checkpoint.is_none() || self.guard.steps_since_checkpoint >= CHECKPOINT_INTERVAL {
+            // The checkpoint must not contain the guard, or it would contain all older checkpoints
+            let kept = self.take_kept();
+            let checkpoint = self.clone();
+            self.restore_kept(kept);
+            self.guard.checkpoint = Some(Box::new(checkpoint));
+            self.guard.steps_since_checkpoint = 0;
+        }
+
+        let settings = self.guard.settings;
+        let strain = if settings.adaptive_substeps || self.guard.substeps > 1 { self.max_strain() } else { 0.0 };
+        if settings.adaptive_substeps && strain > HIGH_STRAIN && self.guard.substeps < settings.max_substeps {
+            self.guard.substeps = (self.guard.substeps * 2).min(settings.max_substeps);
+            self.guard.relaxed_steps = 0;
+        } else if strain < LOW_STRAIN {
+            self.guard.relaxed_steps += 1;
+            if self.guard.relaxed_steps >= RELAX_STEPS && self.guard.substeps > 1 {
+                self.guard.substeps /= 2;
+                self.guard.relaxed_steps = 0;
+            }
+        } else {
+            self.guard.relaxed_steps = 0;
+        }
+    }
+
+
+    // Largest strain of any link
+    fn max_strain(&self) -> f32 {
+        self.links.iter().map(|link| {
+            let length = self.positions[link.from_idx].distance(self.positions[link.to_idx]);
+            link.strain(length).abs()
+        }).fold(0.0, f32::max)
+    }
+
+
+    // Returns false if there is no checkpoint to go back to, or if the substeps cant be raised anymore
+    fn rollback(&mut self) -> bool {
+        if self.guard.substeps >= self.guard.settings.max_substeps {
+            return false;
+        }
+        let Some(checkpoint) = self.guard.checkpoint.clone() else {
+            return false;
+        };
+        let mut kept = self.take_kept();
+        let grabbed = std::mem::take(&mut self.grabbed);
+        let targets = self.ik_chains.iter().map(|chain| chain.target_position).collect::<Vec<_>>();
+        let grab_strength = self.grab_strength;
+        *self = *checkpoint;
+        // The mouse and the IK targets dont go back in time
+        self.grabbed = grabbed;
+        self.grab_strength = grab_strength;
+        for (chain, target) in self.ik_chains.iter_mut().zip(targets) {
+            chain.target_position = target;
+        }
+        // Links that broke since the checkpoint are back, so the render snapshot has to take over all of them
+        kept.topology_events.push(TopologyEvent::Reset(self.links.clone(), self.islands.clone()));
+        self.islands.wake_all();
+        kept.guard.substeps = (kept.guard.substeps * 2).min(kept.guard.settings.max_substeps);
+        kept.guard.relaxed_steps = 0;
+        self.restore_kept(kept);
+        true
+    }
+
+
+    fn take_kept(&mut self) -> Kept {
+        Kept {
+            guard: std::mem::take(&mut self.guard),
+            topology_events: std::mem::take(&mut self.topology_events),
+            energy_samples: std::mem::take(&mut self.energy_samples),
+            measure_energy: self.measure_energy,
+            link_breaks: std::mem::take(&mut self.link_breaks),
+            timings: std::mem::take(&mut self.timings),
+            steps: self.steps,
+            recording: self.recording.take(),
+            player: self.player.take(),
+            replay_events: std::mem::take(&mut self.replay_events),
+        }
+    }
+
+
+    fn restore_kept(&mut self, kept: Kept) {
+        self.guard = kept.guard;
+        self.topology_events = kept.topology_events;
+        self.energy_samples = kept.energy_samples;
+        self.measure_energy = kept.measure_energy;
+        self.link_breaks = kept.link_breaks;
+        self.timings = kept.timings;
+        self.steps = kept.steps;
+        self.recording = kept.recording;
+        self.player = kept.player;
+        self.replay_events = kept.replay_events;
+    }
+
+
+    // Moves the points to a finite position inside of the bounds and stops them
+    fn clamp_points(&mut self, points: &[usize]) {
+        for idx in points.iter().copied() {
+            let mut pos = self.positions[idx];
+            if !pos.is_finite() {
+                pos = if self.prev_positions[idx].is_finite() { self.prev_positions[idx] } else { self.bounds * 0.5 };
+            }
+            let pos = pos.clamp(Vec2::ZERO, self.bounds);
+            self.positions[idx] = pos;
+            self.prev_positions[idx] = pos;
+            self.islands.wake_point(idx);
+        }
+    }
+}
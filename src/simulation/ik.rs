@@ -7,20 +7,19 @@ use super::SimulationState;
 pub struct IKChain {
     pub(super) links: Vec<usize>,
     pub target_position: Vec2,
-    error_margin: f32,
-    num_iterations: usize,
+    pub(super) error_margin: f32,
+    pub(super) num_iterations: usize,
     // Maximum angle (deg) that the link can rotate from its parents direction
-    max_angle_per_link: f32,
+    pub(super) max_angle_per_link: f32,
     // Gets recalculated each frame by the sum of length of all links
     pub(super) current_max_length: f32,
 }
-#[allow(dead_code)]
 impl IKChain {
     pub fn new(links: Vec<usize>) -> Self {
         Self {
             links,
             target_position: Vec2::ZERO,
-            error_margin: 1.0,
+            error_margin: 0.01,
             num_iterations: 8,
             max_angle_per_link: 45.0,
             current_max_length: 0.0,
@@ -46,27 +45,19 @@ impl IKChain {
 
 
 // Without this extra error margin, the chain would sometimes "snap" to the straightened state
-// when the target is "just" out of reach.
-const FABRIK_EXTRA_ERROR_MARGIN: f32 = 50.0;
+// when the target is "just" out of reach. Both margins are in meters
+const FABRIK_EXTRA_ERROR_MARGIN: f32 = 0.5;
 #[allow(non_snake_case)]
-pub fn solve_FABRIK(next_state: &mut SimulationState, previous_state: &SimulationState) {
-    for chain_idx in 0..previous_state.ik_chains.len() {
-        let chain = &previous_state.ik_chains[chain_idx];
-        // "Cut" the chain, when a link has been removed this frame
-        // Use this instead of chain.links so we can react if a link has just been destroyed
-        let chain_links = &previous_state.ik_chains[chain_idx].links.iter().map_while(|link_idx| {
-            if !next_state.removed_link_indices.contains(link_idx) {
-                Some(*link_idx)
-            } else {
-                None
-            }
-        }).collect::<Vec<usize>>();
-        next_state.ik_chains[chain_idx].links.clone_from(chain_links);
+pub fn solve_FABRIK(state: &mut SimulationState) {
+    // Chains get cut in SimulationState::remove_links, as soon as one of their links is removed
+    let SimulationState { ik_chains, links, positions, .. } = state;
+    for chain in ik_chains.iter_mut() {
+        let chain_links = &chain.links;
         if chain_links.is_empty() {
             continue;
         }
 
-        let start_pos = previous_state.positions[chain_links[0]];
+        let start_pos = positions[chain_links[0]];
         let target_pos = chain.target_position;
         
         let diff = target_pos - start_pos;
@@ -74,16 +65,16 @@ pub fn solve_FABRIK(next_state: &mut SimulationState, previous_state: &Simulatio
 
         // Recalculate the max length in case it has changed (for example by user input)
         let chain_max_length = chain_links.iter().map(|link| {
-            let link = &previous_state.links[*link];
+            let link = &links[*link];
             link.max_length
         }).sum::<f32>();
         // If we cant even reach the target, point each link straight towards the target and be done
         if chain_max_length < diff.length() - chain.error_margin - FABRIK_EXTRA_ERROR_MARGIN {
             let mut prev_pos = start_pos;
             for link_idx in chain_links.iter() {
-                let link = &next_state.links[*link_idx];
+                let link = &links[*link_idx];
                 let next_pos = prev_pos + dir_to_target * link.max_length;
-                next_state.positions[link.to_idx] = next_pos;
+                positions[link.to_idx] = next_pos;
                 prev_pos = next_pos;
             }
             continue;
@@ -94,14 +85,14 @@ pub fn solve_FABRIK(next_state: &mut SimulationState, previous_state: &Simulatio
         let mut point_indices = vec![];
         let mut link_lengths = vec![];
         for link_idx in chain_links {
-            let link = &previous_state.links[*link_idx];
+            let link = &links[*link_idx];
             if !point_indices.contains(&link.from_idx) {
                 point_indices.push(link.from_idx);
-                point_positions.push(previous_state.positions[link.from_idx]);
+                point_positions.push(positions[link.from_idx]);
             }
             if !point_indices.contains(&link.to_idx) {
                 point_indices.push(link.to_idx);
-                point_positions.push(previous_state.positions[link.to_idx]);
+                point_positions.push(positions[link.to_idx]);
             }
             link_lengths.push(link.max_length);
         }
@@ -151,12 +142,12 @@ pub fn solve_FABRIK(next_state: &mut SimulationState, previous_state: &Simulatio
             }
         }
 
-        // Write the temporary positions back into the next state
+        // Write the temporary positions back into the state
         point_indices.iter().for_each(|idx| {
-            next_state.positions[*idx] = point_positions[*idx];
+            positions[*idx] = point_positions[*idx];
         });
 
-        next_state.ik_chains[chain_idx].current_max_length = chain_max_length;
+        chain.current_max_length = chain_max_length;
     }
 }
 
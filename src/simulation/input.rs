@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use macroquad::{prelude::*, ui};
+use miniquad::window::screen_size;
+
+use super::{camera::Camera, Simulation};
+
+
+/// Everything the tools read from the mouse and keyboard in one frame.
+/// `update` fills it from macroquad, scripts and tests can fill it by hand and pass it to `update_headless`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputState {
+    /// Mouse position in screen pixels
+    pub mouse_position: Vec2,
+    /// Mouse position in the frame before, in screen pixels
+    pub prev_mouse_position: Vec2,
+    pub buttons_down: HashSet<MouseButton>,
+    /// Buttons that went down this frame
+    pub buttons_pressed: HashSet<MouseButton>,
+    pub keys_down: HashSet<KeyCode>,
+    /// Keys that went down this frame
+    pub keys_pressed: HashSet<KeyCode>,
+    /// Vertical movement of the wheel, only its direction is used
+    pub wheel: f32,
+    /// The mouse is over a window of the ui, so clicks belong to the window
+    pub mouse_over_ui: bool,
+    /// Size of the screen in pixels
+    pub screen_size: Vec2,
+}
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            mouse_position: Vec2::ZERO,
+            prev_mouse_position: Vec2::ZERO,
+            buttons_down: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            keys_down: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            wheel: 0.0,
+            mouse_over_ui: false,
+            screen_size: Camera::DEFAULT_SCREEN_SIZE,
+        }
+    }
+}
+impl InputState {
+    pub fn from_macroquad() -> Self {
+        let mouse_position = Vec2::from(mouse_position());
+        let buttons = [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
+        Self {
+            mouse_position,
+            prev_mouse_position: mouse_position - mouse_delta_position() * Vec2::from(screen_size()),
+            buttons_down: buttons.iter().copied().filter(|button| is_mouse_button_down(*button)).collect(),
+            buttons_pressed: buttons.iter().copied().filter(|button| is_mouse_button_pressed(*button)).collect(),
+            keys_down: get_keys_down(),
+            keys_pressed: get_keys_pressed(),
+            wheel: mouse_wheel().1,
+            mouse_over_ui: ui::root_ui().is_mouse_over(mouse_position),
+            screen_size: Vec2::from(screen_size()),
+        }
+    }
+
+
+    /// The mouse at the screen position, without having moved
+    pub fn at(mouse_position: Vec2) -> Self {
+        Self {
+            mouse_position,
+            prev_mouse_position: mouse_position,
+            ..Default::default()
+        }
+    }
+
+
+    pub fn screen_size(mut self, val: Vec2) -> Self {
+        self.screen_size = val;
+        self
+    }
+
+
+    /// Holds the button down, after pressing it in this frame
+    pub fn press(mut self, button: MouseButton) -> Self {
+        self.buttons_down.insert(button);
+        self.buttons_pressed.insert(button);
+        self
+    }
+
+
+    pub fn hold(mut self, button: MouseButton) -> Self {
+        self.buttons_down.insert(button);
+        self
+    }
+
+
+    /// Holds the key down, after pressing it in this frame
+    pub fn press_key(mut self, key: KeyCode) -> Self {
+        self.keys_down.insert(key);
+        self.keys_pressed.insert(key);
+        self
+    }
+
+
+    pub fn hold_key(mut self, key: KeyCode) -> Self {
+        self.keys_down.insert(key);
+        self
+    }
+
+
+    /// The next frame, with the mouse moved to the screen position and everything still held down
+    pub fn moved_to(&self, mouse_position: Vec2) -> Self {
+        Self {
+            mouse_position,
+            prev_mouse_position: self.mouse_position,
+            buttons_down: self.buttons_down.clone(),
+            buttons_pressed: HashSet::new(),
+            keys_down: self.keys_down.clone(),
+            keys_pressed: HashSet::new(),
+            wheel: 0.0,
+            mouse_over_ui: self.mouse_over_ui,
+            screen_size: self.screen_size,
+        }
+    }
+
+
+    /// The next frame, with the button let go
+    pub fn released(&self, button: MouseButton) -> Self {
+        let mut next = self.moved_to(self.mouse_position);
+        next.buttons_down.remove(&button);
+        next
+    }
+
+
+    /// Frames of pressing the button at `from`, moving to `to` in `moves` even steps and letting go there.
+    /// In screen pixels, see `Simulation::world_to_screen`
+    pub fn drag(button: MouseButton, from: Vec2, to: Vec2, moves: usize) -> Vec<Self> {
+        let mut frames = vec![InputState::at(from).press(button)];
+        for i in 1..=moves {
+            let next = frames[i - 1].moved_to(from.lerp(to, i as f32 / moves as f32));
+            frames.push(next);
+        }
+        let last = frames[moves].released(button);
+        frames.push(last);
+        frames
+    }
+
+
+    /// The right mouse drag that cuts every link it crosses
+    pub fn swipe(from: Vec2, to: Vec2, moves: usize) -> Vec<Self> {
+        InputState::drag(MouseButton::Right, from, to, moves)
+    }
+
+
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+
+    pub fn is_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+
+    pub fn shift(&self) -> bool {
+        self.is_key_down(KeyCode::LeftShift) || self.is_key_down(KeyCode::RightShift)
+    }
+
+
+    pub fn ctrl(&self) -> bool {
+        self.is_key_down(KeyCode::LeftControl) || self.is_key_down(KeyCode::RightControl)
+    }
+
+
+    /// The mouse moved since the last frame
+    pub fn mouse_moved(&self) -> bool {
+        self.mouse_position != self.prev_mouse_position
+    }
+}
+
+
+impl Simulation {
+    // Mouse position of the current input in world units
+    pub(super) fn mouse_position(&self) -> Vec2 {
+        self.camera.screen_to_world(self.input.mouse_position)
+    }
+
+
+    /// Screen position of the world position, for scripted input
+    pub fn world_to_screen(&self, pos: Vec2) -> Vec2 {
+        self.camera.world_to_screen(pos)
+    }
+}
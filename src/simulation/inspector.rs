@@ -0,0 +1,175 @@
+use macroquad::{prelude::*, ui::{self, hash}};
+
+use crate::ui::{colorbox, NumberField};
+use super::{selection::Selection, Command, LinkRef, Simulation};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InspectorTarget {
+    Point(usize),
+    Link(usize),
+}
+
+
+// Text of the numeric editors, which is kept while the same point or link stays selected
+#[derive(Debug, Default)]
+pub(super) struct Inspector {
+    target: Option<InspectorTarget>,
+    mass: NumberField,
+    x: NumberField,
+    y: NumberField,
+    velocity_x: NumberField,
+    velocity_y: NumberField,
+    min_length: NumberField,
+    max_length: NumberField,
+    stiffness: NumberField,
+    damping: NumberField,
+    max_stress: NumberField,
+}
+impl Inspector {
+    // Throws away unapplied edits when something else gets inspected
+    fn show(&mut self, target: InspectorTarget) {
+        if self.target == Some(target) {
+            return;
+        }
+        self.target = Some(target);
+        for field in [&mut self.mass, &mut self.x, &mut self.y, &mut self.velocity_x, &mut self.velocity_y,
+            &mut self.min_length, &mut self.max_length, &mut self.stiffness, &mut self.damping, &mut self.max_stress] {
+            field.reset();
+        }
+    }
+}
+
+
+impl Simulation {
+    pub(super) fn draw_point_inspector(&mut self, point_idx: usize) {
+        self.inspector.show(InspectorTarget::Point(point_idx));
+        let color_picker_texture = self.color_picker_texture();
+        let snapshot = &self.snapshot;
+        let position = snapshot.positions[point_idx];
+        // The positions are one step apart
+        let delta = snapshot.step_delta.max(f32::EPSILON);
+        let velocity = (position - snapshot.prev_positions[point_idx]) / delta;
+        let max_velocity = Simulation::MAX_VELOCITY;
+        let bounds = self.bounds;
+        let attached_links = snapshot.links.iter().enumerate()
+            .filter(|(_, link)| link.from_idx == point_idx || link.to_idx == point_idx)
+            .map(|(i, link)| (i, if link.from_idx == point_idx { link.to_idx } else { link.from_idx }))
+            .collect::<Vec<_>>();
+        let mut color = snapshot.colors[point_idx];
+        let mut fixed = snapshot.fixed[point_idx];
+        let mut commands = vec![];
+        let mut selected_link = None;
+
+        let inspector = &mut self.inspector;
+        ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(250.0, 400.0))
+            .label(&format!("Editing Point {}", point_idx))
+            .movable(false)
+            .ui(&mut ui::root_ui(), |ui| {
+                colorbox(
+                    ui,
+                    hash!(),
+                    "Start color",
+                    &mut color,
+                    color_picker_texture.clone(),
+                );
+                ui.checkbox(hash!(), "Fixed", &mut fixed);
+                if let Some(mass) = inspector.mass.ui(ui, hash!(), "Mass (kg)", snapshot.masses[point_idx], 0.01..=1000.0) {
+                    commands.push(Command::SetMass(point_idx, mass));
+                }
+
+                ui.label(None, "Position (m)");
+                if let Some(x) = inspector.x.ui(ui, hash!(), "X", position.x, 0.0..=bounds.x) {
+                    commands.push(Command::MovePoint(point_idx, vec2(x, position.y)));
+                }
+                if let Some(y) = inspector.y.ui(ui, hash!(), "Y", position.y, 0.0..=bounds.y) {
+                    commands.push(Command::MovePoint(point_idx, vec2(position.x, y)));
+                }
+
+                ui.label(None, &format!("Velocity ({:.2} m/s)", velocity.length()));
+                if let Some(x) = inspector.velocity_x.ui(ui, hash!(), "X ", velocity.x, -max_velocity..=max_velocity) {
+                    commands.push(Command::SetVelocity(point_idx, vec2(x, velocity.y)));
+                }
+                if let Some(y) = inspector.velocity_y.ui(ui, hash!(), "Y ", velocity.y, -max_velocity..=max_velocity) {
+                    commands.push(Command::SetVelocity(point_idx, vec2(velocity.x, y)));
+                }
+
+                ui.label(None, &format!("Links ({})", attached_links.len()));
+                for (link_idx, other_idx) in attached_links.iter() {
+                    if ui.button(None, format!("Link {} to point {}", link_idx, other_idx)) {
+                        selected_link = Some(*link_idx);
+                    }
+                }
+        });
+
+        if color != snapshot.colors[point_idx] {
+            commands.push(Command::SetPointColor(point_idx, color));
+        }
+        if fixed != snapshot.fixed[point_idx] {
+            commands.push(Command::SetFixed(point_idx, fixed));
+        }
+        for command in commands {
+            self.push_command(command);
+        }
+        if let Some(link_idx) = selected_link {
+            self.selection = Selection { points: vec![], links: vec![link_idx] };
+        }
+    }
+
+
+    pub(super) fn draw_link_inspector(&mut self, link_idx: usize) {
+        self.inspector.show(InspectorTarget::Link(link_idx));
+        let link = &self.snapshot.links[link_idx];
+        let length = self.snapshot.positions[link.from_idx].distance(self.snapshot.positions[link.to_idx]);
+        let (from_mass, to_mass) = (self.snapshot.masses[link.from_idx], self.snapshot.masses[link.to_idx]);
+        let load = Simulation::link_offset(link, &self.snapshot.positions)
+            .map_or(0.0, |offset| Simulation::link_load(offset, from_mass, to_mass, self.snapshot.step_delta));
+        let mut params = link.clone();
+        let mut selected_point = None;
+
+        let inspector = &mut self.inspector;
+        ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(250.0, 400.0))
+            .label(&format!("Editing Link {}", link_idx))
+            .movable(false)
+            .ui(&mut ui::root_ui(), |ui| {
+                if ui.button(None, format!("Point {}", link.from_idx)) {
+                    selected_point = Some(link.from_idx);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, format!("Point {}", link.to_idx)) {
+                    selected_point = Some(link.to_idx);
+                }
+
+                ui.label(None, "Rest lengths (m)");
+                if let Some(val) = inspector.min_length.ui(ui, hash!(), "Min", link.min_length, 0.0..=link.max_length) {
+                    params.min_length = val;
+                }
+                // Links without a max length use f32::MAX, infinite lengths dont pass Link::validate
+                if let Some(val) = inspector.max_length.ui(ui, hash!(), "Max", link.max_length, link.min_length..=f32::MAX) {
+                    params.max_length = val;
+                }
+                if let Some(val) = inspector.stiffness.ui(ui, hash!(), "Stiffness", link.stiffness, 0.0..=1.0) {
+                    params.stiffness = val;
+                }
+                if let Some(val) = inspector.damping.ui(ui, hash!(), "Damping", link.damping, 0.0..=1.0) {
+                    params.damping = val;
+                }
+                if let Some(val) = inspector.max_stress.ui(ui, hash!(), "Break load (N)", link.max_stress, 0.0..=f32::INFINITY) {
+                    params.max_stress = val;
+                }
+
+                ui.label(None, &format!("Length: {:.3} m", length));
+                ui.label(None, &format!("Strain: {:.1}%", link.strain(length) * 100.0));
+                ui.label(None, &format!("Load: {:.1} / {} N", load, link.max_stress));
+        });
+
+        let current = &self.snapshot.links[link_idx];
+        if (params.min_length, params.max_length, params.stiffness, params.damping, params.max_stress)
+            != (current.min_length, current.max_length, current.stiffness, current.damping, current.max_stress) {
+            self.push_command(Command::SetLinkParams(LinkRef::new(link_idx, current), params));
+        }
+        if let Some(point_idx) = selected_point {
+            self.selection = Selection { points: vec![point_idx], links: vec![] };
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use macroquad::math::Vec2;
+
+use super::{remap_index, remove_indices};
+
+
+// A group of points that are connected through links
+#[derive(Debug, Clone, Default)]
+pub(super) struct Island {
+    // Stays the same while the island exists, unlike its index
+    pub(super) id: u64,
+    pub(super) points: Vec<usize>,
+    // Time (in seconds) all points of the island have been moving slower than the sleep velocity
+    sleep_timer: f32,
+    pub(super) asleep: bool,
+    // Islands that get moved from the outside (kinematic points, IK chains, grabbed points) never sleep
+    pub(super) driven: bool,
+    // Lost a link, so it might have fallen apart
+    check_split: bool,
+}
+
+
+/// A group of points that are connected through links
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub id: u64,
+    pub points: Vec<usize>,
+    // Bounding box of the points
+    pub min: Vec2,
+    pub max: Vec2,
+    pub mass: f32,
+    pub center_of_mass: Vec2,
+}
+
+
+/// An island that fell apart because links were removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodySplit {
+    // Id of the island before the split, it is kept by the largest piece
+    pub id: u64,
+    // Ids of all the pieces, including `id`
+    pub pieces: Vec<u64>,
+}
+
+
+// Connected components of the link graph. Adding links merges islands, removing links only
+// searches through the island the link was part of, so nothing has to be rebuilt from scratch
+#[derive(Debug, Clone, Default)]
+pub(super) struct Islands {
+    pub(super) islands: Vec<Island>,
+    // Index into islands for each point
+    pub(super) point_island: Vec<usize>,
+    // Points each point is linked to, once for every link
+    neighbors: Vec<Vec<usize>>,
+    next_id: u64,
+    // Set when points or links were added or removed
+    pub(super) topology_changed: bool,
+}
+impl Islands {
+    // New points start out as their own island
+    pub(super) fn add_points(&mut self, count: usize) {
+        for _ in 0..count {
+            let point_idx = self.point_island.len();
+            self.point_island.push(self.islands.len());
+            self.neighbors.push(vec![]);
+            let id = self.new_id();
+            self.islands.push(Island {
+                id,
+                points: vec![point_idx],
+                ..Default::default()
+            });
+        }
+        self.topology_changed = true;
+    }
+
+
+    pub(super) fn add_link(&mut self, from_idx: usize, to_idx: usize) {
+        self.neighbors[from_idx].push(to_idx);
+        self.neighbors[to_idx].push(from_idx);
+        self.topology_changed = true;
+
+        let a = self.point_island[from_idx];
+        let b = self.point_island[to_idx];
+        if a == b {
+            return;
+        }
+        // Move the points of the smaller island into the larger one
+        let (keep, merge) = if self.islands[a].points.len() >= self.islands[b].points.len() { (a, b) } else { (b, a) };
+        let merged = self.islands.swap_remove(merge);
+        let keep = if keep == self.islands.len() { merge } else { keep };
+        // swap_remove moved the last island into the freed index
+        if merge < self.islands.len() {
+            for point_idx in self.islands[merge].points.iter() {
+                self.point_island[*point_idx] = merge;
+            }
+        }
+        for point_idx in merged.points.iter() {
+            self.point_island[*point_idx] = keep;
+        }
+        let island = &mut self.islands[keep];
+        island.points.extend(merged.points);
+        island.check_split |= merged.check_split;
+        island.asleep = false;
+        island.sleep_timer = 0.0;
+    }
+
+
+    // The island wakes up, since its points can move differently without the link,
+    // and gets checked for splits in the next call to resolve_splits
+    pub(super) fn remove_link(&mut self, from_idx: usize, to_idx: usize) {
+        if let Some(pos) = self.neighbors[from_idx].iter().position(|idx| *idx == to_idx) {
+            self.neighbors[from_idx].swap_remove(pos);
+        }
+        if let Some(pos) = self.neighbors[to_idx].iter().position(|idx| *idx == from_idx) {
+            self.neighbors[to_idx].swap_remove(pos);
+        }
+        let island = &mut self.islands[self.point_island[from_idx]];
+        island.check_split = true;
+        island.asleep = false;
+        island.sleep_timer = 0.0;
+        self.topology_changed = true;
+    }
+
+
+    // The points must not have any links left, which makes each of them its own island
+    pub(super) fn remove_points(&mut self, removed: &[usize]) {
+        self.islands.retain(|island| !island.points.iter().all(|idx| removed.binary_search(idx).is_ok()));
+        for island in self.islands.iter_mut() {
+            island.points = island.points.iter().filter_map(|idx| remap_index(*idx, removed)).collect();
+        }
+        remove_indices(&mut self.neighbors, removed);
+        for neighbors in self.neighbors.iter_mut() {
+            for neighbor in neighbors.iter_mut() {
+                *neighbor -= removed.partition_point(|idx| idx < neighbor);
+            }
+        }
+        self.point_island.truncate(self.point_island.len() - removed.len());
+        for (island_idx, island) in self.islands.iter().enumerate() {
+            for point_idx in island.points.iter() {
+                self.point_island[*point_idx] = island_idx;
+            }
+        }
+        self.topology_changed = true;
+    }
+
+
+    // Splits every island that lost links and isnt connected anymore. The pieces are awake
+    pub(super) fn resolve_splits(&mut self) -> Vec<BodySplit> {
+        let mut splits = vec![];
+        let mut visited = vec![];
+        for island_idx in 0..self.islands.len() {
+            if !self.islands[island_idx].check_split {
+                continue;
+            }
+            self.islands[island_idx].check_split = false;
+
+            // Flood fill the island piece by piece. The points are sorted, so the visited flag
+            // of a point can be found with a binary search
+            let mut pieces: Vec<Vec<usize>> = vec![];
+            let mut points = std::mem::take(&mut self.islands[island_idx].points);
+            points.sort_unstable();
+            visited.clear();
+            visited.resize(points.len(), false);
+            for start in 0..points.len() {
+                if visited[start] {
+                    continue;
+                }
+                visited[start] = true;
+                let mut piece = vec![points[start]];
+                let mut next = 0;
+                while next < piece.len() {
+                    for neighbor in self.neighbors[piece[next]].iter() {
+                        if let Ok(idx) = points.binary_search(neighbor) {
+                            if !visited[idx] {
+                                visited[idx] = true;
+                                piece.push(*neighbor);
+                            }
+                        }
+                    }
+                    next += 1;
+                }
+                pieces.push(piece);
+            }
+
+            if pieces.len() == 1 {
+                self.islands[island_idx].points = pieces.pop().unwrap_or_default();
+                continue;
+            }
+
+            // The largest piece keeps the island, the rest become new islands
+            pieces.sort_by_key(|piece| std::cmp::Reverse(piece.len()));
+            let island = &mut self.islands[island_idx];
+            island.points = pieces.remove(0);
+            island.asleep = false;
+            island.sleep_timer = 0.0;
+            let mut split = BodySplit { id: island.id, pieces: vec![island.id] };
+            for piece in pieces {
+                let id = self.new_id();
+                for point_idx in piece.iter() {
+                    self.point_island[*point_idx] = self.islands.len();
+                }
+                self.islands.push(Island {
+                    id,
+                    points: piece,
+                    ..Default::default()
+                });
+                split.pieces.push(id);
+            }
+            splits.push(split);
+        }
+        splits
+    }
+
+
+    pub(super) fn component(&self, island: &Island, positions: &[Vec2], masses: &[f32]) -> Component {
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        let mut mass = 0.0;
+        let mut weighted_positions = Vec2::ZERO;
+        for idx in island.points.iter() {
+            min = min.min(positions[*idx]);
+            max = max.max(positions[*idx]);
+            mass += masses[*idx];
+            weighted_positions += positions[*idx] * masses[*idx];
+        }
+        Component {
+            id: island.id,
+            points: island.points.clone(),
+            min,
+            max,
+            mass,
+            center_of_mass: weighted_positions / mass.max(f32::EPSILON),
+        }
+    }
+
+
+    fn new_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+
+    pub(super) fn is_asleep(&self, point_idx: usize) -> bool {
+        self.point_island.get(point_idx).is_some_and(|island_idx| self.islands[*island_idx].asleep)
+    }
+
+
+    pub(super) fn wake_point(&mut self, point_idx: usize) {
+        if let Some(island_idx) = self.point_island.get(point_idx) {
+            let island = &mut self.islands[*island_idx];
+            island.asleep = false;
+            island.sleep_timer = 0.0;
+        }
+    }
+
+
+    pub(super) fn wake_all(&mut self) {
+        for island in self.islands.iter_mut() {
+            island.asleep = false;
+            island.sleep_timer = 0.0;
+        }
+    }
+
+
+    pub(super) fn set_driven_points(&mut self, driven_points: impl Iterator<Item = usize>) {
+        for island in self.islands.iter_mut() {
+            island.driven = false;
+        }
+        for point_idx in driven_points {
+            let island = &mut self.islands[self.point_island[point_idx]];
+            island.driven = true;
+            island.asleep = false;
+        }
+    }
+
+
+    // Puts islands to sleep once all of their points have been slow for long enough.
+    // Sleeping points get their velocity removed, so they dont jump once they wake up again
+    pub(super) fn update_sleep(&mut self, positions: &[Vec2], prev_positions: &mut [Vec2], sleep_velocity: f32, sleep_time: f32, delta: f32) {
+        for island in self.islands.iter_mut() {
+            if island.asleep || island.driven {
+                continue;
+            }
+            let is_slow = island.points.iter().all(|idx| positions[*idx].distance_squared(prev_positions[*idx]) < sleep_velocity * sleep_velocity);
+            if !is_slow {
+                island.sleep_timer = 0.0;
+                continue;
+            }
+            island.sleep_timer += delta;
+            if island.sleep_timer >= sleep_time {
+                island.asleep = true;
+                for idx in island.points.iter() {
+                    prev_positions[*idx] = positions[*idx];
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::{BodySplit, Islands};
+
+
+    const SLEEP_VELOCITY: f32 = 0.01;
+    const SLEEP_TIME: f32 = 1.0;
+    const DELTA: f32 = 0.25;
+
+
+    // Points 0-1-2-3 linked in a row
+    fn row() -> Islands {
+        let mut islands = Islands::default();
+        islands.add_points(4);
+        for i in 0..3 {
+            islands.add_link(i, i + 1);
+        }
+        islands
+    }
+
+
+    // Steps the sleep timers with points that dont move, until the sleep time is over
+    fn rest(islands: &mut Islands) {
+        let positions = vec![Vec2::ZERO; islands.point_island.len()];
+        let mut prev_positions = positions.clone();
+        for _ in 0..(SLEEP_TIME / DELTA) as usize {
+            islands.update_sleep(&positions, &mut prev_positions, SLEEP_VELOCITY, SLEEP_TIME, DELTA);
+        }
+    }
+
+
+    #[test]
+    fn links_merge_islands() {
+        let islands = row();
+        assert_eq!(islands.islands.len(), 1);
+        assert!(islands.point_island.iter().all(|island_idx| *island_idx == 0));
+    }
+
+
+    #[test]
+    fn removing_a_link_splits_the_island() {
+        let mut islands = row();
+        let id = islands.islands[0].id;
+        islands.remove_link(1, 2);
+        let splits = islands.resolve_splits();
+        assert_eq!(splits.len(), 1);
+        let BodySplit { id: split_id, pieces } = &splits[0];
+        assert_eq!(*split_id, id);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(islands.islands.len(), 2);
+        assert_eq!(islands.point_island[0], islands.point_island[1]);
+        assert_eq!(islands.point_island[2], islands.point_island[3]);
+        assert_ne!(islands.point_island[1], islands.point_island[2]);
+    }
+
+
+    #[test]
+    fn removing_a_link_of_a_loop_doesnt_split_it() {
+        let mut islands = row();
+        islands.add_link(3, 0);
+        islands.remove_link(1, 2);
+        assert!(islands.resolve_splits().is_empty());
+        assert_eq!(islands.islands.len(), 1);
+    }
+
+
+    #[test]
+    fn resting_islands_sleep_until_woken() {
+        let mut islands = row();
+        rest(&mut islands);
+        assert!(islands.is_asleep(0));
+        islands.wake_point(3);
+        assert!(!islands.is_asleep(0));
+
+        // Losing a link wakes the island, even when it doesnt split
+        rest(&mut islands);
+        islands.add_link(3, 0);
+        rest(&mut islands);
+        islands.remove_link(1, 2);
+        assert!(!islands.is_asleep(0));
+    }
+
+
+    #[test]
+    fn driven_islands_dont_sleep() {
+        let mut islands = row();
+        islands.add_points(1);
+        islands.set_driven_points([0].into_iter());
+        rest(&mut islands);
+        assert!(!islands.is_asleep(0));
+        assert!(islands.is_asleep(4));
+    }
+}
@@ -0,0 +1,168 @@
+use macroquad::math::Vec2;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    SineInOut,
+}
+impl Easing {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                -1.0 + (4.0 - 2.0 * t) * t
+            },
+            Easing::SineInOut => 0.5 - 0.5 * (t * std::f32::consts::PI).cos(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+    // Stops at the end of the path
+    Once,
+    // Jumps back to the start of the path. Unless the path ends where it starts,
+    // the jump shows up as a huge velocity of the point
+    Loop,
+    // Runs the path backwards after reaching the end
+    PingPong,
+}
+
+
+#[derive(Debug, Clone)]
+pub enum PathShape {
+    Line { from: Vec2, to: Vec2 },
+    // One full revolution per cycle, starting at `start_angle` (deg)
+    Circle { center: Vec2, radius: f32, start_angle: f32 },
+    // Cubic bezier curve
+    Bezier { p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2 },
+    // List of (time in seconds, position), sorted by time
+    Keyframes(Vec<(f32, Vec2)>),
+}
+
+
+/// A scripted path that a kinematic point follows.
+/// The point is moved by the path and ignores forces and links, but still pulls on everything linked to it.
+#[derive(Debug, Clone)]
+pub struct KinematicPath {
+    pub(super) shape: PathShape,
+    // Length of one cycle in seconds
+    pub(super) duration: f32,
+    pub(super) easing: Easing,
+    pub(super) looping: LoopMode,
+    // Shifts the path in time, useful for having multiple points run the same path out of phase
+    pub(super) time_offset: f32,
+}
+impl KinematicPath {
+    pub fn new(shape: PathShape, duration: f32) -> Self {
+        Self {
+            shape,
+            duration,
+            easing: Easing::Linear,
+            looping: LoopMode::Loop,
+            time_offset: 0.0,
+        }
+    }
+
+    pub fn line(from: Vec2, to: Vec2, duration: f32) -> Self {
+        Self::new(PathShape::Line { from, to }, duration).looping(LoopMode::PingPong)
+    }
+
+    pub fn circle(center: Vec2, radius: f32, duration: f32) -> Self {
+        Self::new(PathShape::Circle { center, radius, start_angle: 0.0 }, duration)
+    }
+
+    pub fn bezier(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, duration: f32) -> Self {
+        Self::new(PathShape::Bezier { p0, p1, p2, p3 }, duration).looping(LoopMode::PingPong)
+    }
+
+    /// The duration is taken from the time of the last keyframe.
+    /// The easing gets applied to every segment between two keyframes instead of the whole path.
+    /// Runs back and forth like lines, set `LoopMode::Loop` for keyframes that end where they start
+    pub fn keyframes(mut frames: Vec<(f32, Vec2)>) -> Self {
+        frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let duration = frames.last().map_or(0.0, |frame| frame.0);
+        Self::new(PathShape::Keyframes(frames), duration).looping(LoopMode::PingPong)
+    }
+
+    pub fn easing(mut self, val: Easing) -> Self {
+        self.easing = val;
+        self
+    }
+    pub fn looping(mut self, val: LoopMode) -> Self {
+        self.looping = val;
+        self
+    }
+    pub fn time_offset(mut self, val: f32) -> Self {
+        self.time_offset = val;
+        self
+    }
+    pub fn start_angle(mut self, val: f32) -> Self {
+        if let PathShape::Circle { start_angle, .. } = &mut self.shape {
+            *start_angle = val;
+        }
+        self
+    }
+
+
+    /// Returns the position on the path at the given simulation time (in seconds)
+    pub fn sample(&self, time: f32) -> Vec2 {
+        let progress = self.progress(time);
+        match &self.shape {
+            PathShape::Line { from, to } => from.lerp(*to, self.easing.apply(progress)),
+            PathShape::Circle { center, radius, start_angle } => {
+                let angle = start_angle.to_radians() + self.easing.apply(progress) * std::f32::consts::TAU;
+                *center + Vec2::new(angle.cos(), angle.sin()) * *radius
+            },
+            PathShape::Bezier { p0, p1, p2, p3 } => {
+                let t = self.easing.apply(progress);
+                let u = 1.0 - t;
+                *p0 * (u * u * u) + *p1 * (3.0 * u * u * t) + *p2 * (3.0 * u * t * t) + *p3 * (t * t * t)
+            },
+            PathShape::Keyframes(frames) => {
+                let Some(first) = frames.first() else {
+                    return Vec2::ZERO;
+                };
+                let frame_time = progress * self.duration;
+                if frame_time <= first.0 {
+                    return first.1;
+                }
+                for window in frames.windows(2) {
+                    let (t0, p0) = window[0];
+                    let (t1, p1) = window[1];
+                    if frame_time <= t1 {
+                        let t = (frame_time - t0) / (t1 - t0).max(f32::EPSILON);
+                        return p0.lerp(p1, self.easing.apply(t));
+                    }
+                }
+                frames[frames.len() - 1].1
+            },
+        }
+    }
+
+
+    // Maps the simulation time into [0, 1] along the path, depending on the loop mode
+    fn progress(&self, time: f32) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        let cycles = ((time + self.time_offset) / self.duration).max(0.0);
+        match self.looping {
+            LoopMode::Once => cycles.min(1.0),
+            LoopMode::Loop => cycles.fract(),
+            LoopMode::PingPong => {
+                let t = (cycles * 0.5).fract() * 2.0;
+                if t > 1.0 { 2.0 - t } else { t }
+            },
+        }
+    }
+}
@@ -7,8 +7,9 @@ pub struct Link {
     pub(crate) max_length: f32,
     pub(crate) stiffness: f32,
     pub(crate) damping: f32,
+    // The link breaks when it has to pull on its points with more than this force (N)
+    pub(crate) max_stress: f32,
 }
-#[allow(dead_code)]
 impl Link {
     pub fn new(from_idx: usize, to_idx: usize) -> Self {
         Self {
@@ -18,6 +19,7 @@ impl Link {
             max_length: f32::MAX,
             stiffness: 1.0,
             damping: 1.0,
+            max_stress: super::Simulation::MAX_LINK_STRESS,
         }
     }
 
@@ -41,4 +43,34 @@ impl Link {
         self.damping = val;
         self
     }
-}
\ No newline at end of file
+    /// Sets the load (N) at which the link breaks. Use `f32::INFINITY` for links that never break
+    pub fn max_stress(mut self, val: f32) -> Self {
+        self.max_stress = val;
+        self
+    }
+
+    /// How far the link is stretched (positive) or compressed (negative) past its rest lengths, relative to them
+    pub fn strain(&self, length: f32) -> f32 {
+        if length > self.max_length {
+            (length - self.max_length) / self.max_length.max(f32::EPSILON)
+        } else if length < self.min_length {
+            (length - self.min_length) / self.min_length.max(f32::EPSILON)
+        } else {
+            0.0
+        }
+    }
+}
+
+
+/// A link that broke, because it had to pull on its points with more than its max stress
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkBreak {
+    /// Simulated time (s) of the substep it broke in
+    pub time: f32,
+    /// Index of the link right before it was removed. The links that break in the same substep get removed together
+    pub link_idx: usize,
+    pub from_idx: usize,
+    pub to_idx: usize,
+    /// Load (N) that broke it
+    pub load: f32,
+}
@@ -1,16 +1,73 @@
 use macroquad::{prelude::*, ui::{self, hash}};
-use miniquad::window::screen_size;
+use rayon::prelude::*;
 
 mod link;
-pub use link::Link;
+pub use link::{Link, LinkBreak};
 mod point;
 pub use point::Point;
 mod ik;
 pub use ik::IKChain;
-
-use super::ui::colorbox;
+mod kinematic;
+pub use kinematic::{Easing, KinematicPath, LoopMode, PathShape};
+mod grab;
+pub use grab::{GrabMode, GrabSettings, GrabTarget};
+mod explosion;
+pub use explosion::{Explosion, Falloff};
+mod coloring;
+mod snapshot;
+use snapshot::{RenderSnapshot, TopologyEvent};
+mod command;
+pub use command::{Command, LinkRef};
+mod thread;
+use thread::SimulationThread;
+mod island;
+use island::Islands;
+pub use island::{BodySplit, Component};
+mod query;
+use query::SpatialGrid;
+pub use query::{RayHit, RayTarget};
+mod selection;
+use selection::{SelectGesture, Selection};
+pub use selection::SelectShape;
+mod build;
+use build::BuildGesture;
+pub use build::{BuildSettings, Material};
+mod inspector;
+use inspector::Inspector;
+mod camera;
+pub use camera::Camera;
+mod input;
+pub use input::InputState;
+mod render;
+pub use render::{MacroquadRenderer, RenderTexture, Renderer, Space};
+mod svg;
+pub use svg::SvgRenderer;
+mod raster;
+pub use raster::SoftwareRenderer;
+mod export;
+pub use export::FrameSink;
+mod profiler;
+use profiler::{FrameProfile, StepTimings};
+pub use profiler::Profiler;
+mod energy;
+use energy::EnergyMonitor;
+pub use energy::EnergySample;
+mod debug;
+use debug::DebugDraw;
+pub use debug::{DebugOverlays, Heatmap};
+mod guard;
+use guard::Guard;
+pub use guard::{GuardSettings, Instability, InstabilityEvent, RecoveryPolicy};
+mod validation;
+pub use validation::BuildError;
+mod replay;
+use replay::{ReplayEvent, ReplayPlayer};
+pub use replay::{Divergence, Replay};
+mod scene;
+pub use scene::{Scene, SceneError};
 
 
+// Sizes of things drawn on top of the world are in screen pixels, see Camera::pixels
 const POINT_RADIUS: f32 = 7.0;
 const SELECT_COLOR: Color = BLUE;
 // Helps with selection, by extending "collision shape"
@@ -25,12 +82,51 @@ struct SimulationState {
     colors: Vec<Color>,
     fixed: Vec<bool>,
     links: Vec<Link>,
-    removed_link_indices: Vec<usize>,
+    // Added and removed points and links since the last render snapshot, so it doesnt have to copy all links
+    topology_events: Vec<TopologyEvent>,
+    // Links split into batches that dont share any points, see coloring::color_links
+    link_batches: Vec<Vec<usize>>,
+    // Set when links get added or removed, so the batches get rebuilt
+    link_batches_dirty: bool,
+    // Groups of linked points, which get skipped while they are asleep
+    islands: Islands,
     // A list of all the IK chains, represented as list of SimulationState::links indices
     ik_chains: Vec<IKChain>,
+    // Points that follow a scripted path, as (point index, path)
+    kinematic_paths: Vec<(usize, KinematicPath)>,
+    // Simulated time in seconds, used to sample the kinematic paths
+    time: f32,
+    // Points that are pulled towards the mouse
+    grabbed: Vec<GrabTarget>,
+    grab_strength: f32,
+    // Impulses that get applied in the next step, as (point index, impulse)
+    impulses: Vec<(usize, Vec2)>,
+    // Time spent in the steps since the last render snapshot
+    timings: StepTimings,
+    // Energy after every step since the last render snapshot
+    energy_samples: Vec<EnergySample>,
+    // Something outside of the guard wants the energy, see Simulation::measure_energy
+    measure_energy: bool,
+    // Links that broke since the last render snapshot
+    link_breaks: Vec<LinkBreak>,
+    // Time (s) of the last substep, which is how far apart positions and prev_positions are
+    step_delta: f32,
+    // Looks out for explosions after every step, see guard.rs
+    guard: Guard,
+    // Set in deterministic mode: every step takes this long and nothing runs in parallel,
+    // so the same commands always give the same positions
+    fixed_delta: Option<f32>,
+    // Steps since the recording or playback started
+    steps: u64,
+    recording: Option<Box<Replay>>,
+    player: Option<Box<ReplayPlayer>>,
+    // Finished recordings and divergences since the last render snapshot
+    replay_events: Vec<ReplayEvent>,
 
     force: Vec2,
     wall_damping: f32,
+    // The points are kept inside of (0, 0) to bounds
+    bounds: Vec2,
 }
 impl SimulationState {
     pub fn new() -> Self {
@@ -41,380 +137,1427 @@ impl SimulationState {
             colors: vec![],
             fixed: vec![],
             links: vec![],
-            removed_link_indices: vec![],
+            topology_events: vec![],
+            link_batches: vec![],
+            link_batches_dirty: false,
+            islands: Islands::default(),
             ik_chains: vec![],
-            force: Vec2::new(0.0, 200.0),
-            wall_damping: 0.75
+            kinematic_paths: vec![],
+            time: 0.0,
+            grabbed: vec![],
+            grab_strength: 0.0,
+            impulses: vec![],
+            timings: StepTimings::default(),
+            energy_samples: vec![],
+            measure_energy: false,
+            link_breaks: vec![],
+            step_delta: 1.0 / Simulation::THREAD_STEPS_PER_SECOND,
+            guard: Guard::default(),
+            fixed_delta: None,
+            steps: 0,
+            recording: None,
+            player: None,
+            replay_events: vec![],
+            force: Simulation::GRAVITY,
+            wall_damping: 0.75,
+            bounds: Simulation::DEFAULT_BOUNDS,
+        }
+    }
+
+
+    // The solver only runs in parallel outside of deterministic mode, the order of the additions could change otherwise
+    fn parallel(&self) -> bool {
+        Simulation::PARALLEL_SOLVER && self.fixed_delta.is_none()
+    }
+
+
+    fn add_points(&mut self, points: &[Point]) {
+        for point in points {
+            let mut position = point.position;
+            // Kinematic points are fixed as far as the solver is concerned, they only get moved by their path
+            let fixed = point.fixed || point.path.is_some();
+            if let Some(path) = &point.path {
+                position = path.sample(self.time);
+                let idx = self.positions.len();
+                self.kinematic_paths.push((idx, path.clone()));
+            }
+
+            self.positions.push(position);
+            self.prev_positions.push(position);
+            self.masses.push(point.mass);
+            self.colors.push(point.color);
+            self.fixed.push(fixed);
+        }
+        self.islands.add_points(points.len());
+        self.topology_events.push(TopologyEvent::PointsAdded(points.len()));
+    }
+
+
+    fn add_link(&mut self, link: Link) {
+        self.topology_events.push(TopologyEvent::Added(link.clone()));
+        self.islands.add_link(link.from_idx, link.to_idx);
+        self.links.push(link);
+        self.link_batches_dirty = true;
+    }
+
+
+    fn add_ik_chain(&mut self, mut ik_chain: IKChain) {
+        ik_chain.current_max_length = ik_chain.links.iter().map(|link| {
+            let link = &self.links[*link];
+            link.max_length
+        }).sum::<f32>();
+        self.ik_chains.push(ik_chain);
+    }
+
+
+    // Applies the impulse of the explosion to every point inside of its radius
+    // and breaks the links that it loads more than its break threshold
+    fn explode(&mut self, explosion: Explosion) {
+        for i in 0..self.positions.len() {
+            let impulse = explosion.impulse_at(self.positions[i]);
+            if impulse != Vec2::ZERO {
+                self.impulses.push((i, impulse));
+                self.islands.wake_point(i);
+            }
         }
+
+        let Some(threshold) = explosion.break_threshold else {
+            return;
+        };
+        // The load is what it would take to keep the points of the link from moving apart (or together)
+        // within one step, the same load that breaks links in constrain
+        let velocity_change = |idx: usize| {
+            if self.fixed[idx] { Vec2::ZERO } else { explosion.impulse_at(self.positions[idx]) / self.masses[idx].max(f32::EPSILON) }
+        };
+        let delta = self.step_delta;
+        let broken = self.links.iter().enumerate().filter(|(_, link)| {
+            let dir = (self.positions[link.to_idx] - self.positions[link.from_idx]).normalize_or_zero();
+            let offset = dir * (velocity_change(link.to_idx) - velocity_change(link.from_idx)).dot(dir) * delta;
+            Simulation::link_load(offset, self.masses[link.from_idx], self.masses[link.to_idx], delta) > threshold
+        }).map(|(i, _)| i).collect::<Vec<_>>();
+        self.remove_links(&broken);
+    }
+
+
+    // Removes the links at the given indices (sorted, without duplicates)
+    // and updates everything that refers to links by their index
+    fn remove_links(&mut self, removed: &[usize]) {
+        if removed.is_empty() {
+            return;
+        }
+        for link_idx in removed {
+            self.islands.remove_link(self.links[*link_idx].from_idx, self.links[*link_idx].to_idx);
+        }
+        // Only the render snapshot reports the splits
+        self.islands.resolve_splits();
+        remove_indices(&mut self.links, removed);
+        // "Cut" the IK chains at the first removed link
+        for chain in self.ik_chains.iter_mut() {
+            chain.links = chain.links.iter().map_while(|link_idx| remap_index(*link_idx, removed)).collect();
+        }
+        self.topology_events.push(TopologyEvent::Removed(removed.to_vec()));
+        self.link_batches_dirty = true;
+    }
+
+
+    // Removes the points at the given indices (sorted, without duplicates) together with their links,
+    // and updates everything that refers to points by their index
+    fn remove_points(&mut self, removed: &[usize]) {
+        if removed.is_empty() {
+            return;
+        }
+        self.remove_links(&attached_links(&self.links, removed));
+
+        remove_indices(&mut self.positions, removed);
+        remove_indices(&mut self.prev_positions, removed);
+        remove_indices(&mut self.masses, removed);
+        remove_indices(&mut self.colors, removed);
+        remove_indices(&mut self.fixed, removed);
+        shift_link_points(&mut self.links, removed);
+        self.kinematic_paths = std::mem::take(&mut self.kinematic_paths).into_iter()
+            .filter_map(|(point_idx, path)| Some((remap_index(point_idx, removed)?, path)))
+            .collect();
+        self.grabbed.retain_mut(|target| {
+            remap_index(target.point_idx, removed).map(|point_idx| target.point_idx = point_idx).is_some()
+        });
+        self.impulses.retain_mut(|(point_idx, _)| {
+            remap_index(*point_idx, removed).map(|new_idx| *point_idx = new_idx).is_some()
+        });
+        self.islands.remove_points(removed);
+        self.topology_events.push(TopologyEvent::PointsRemoved(removed.to_vec()));
+        self.link_batches_dirty = true;
+    }
+}
+
+
+// Returns the new index of an item after the items in `removed` were removed, None if it was removed itself
+fn remap_index(idx: usize, removed: &[usize]) -> Option<usize> {
+    match removed.binary_search(&idx) {
+        Ok(_) => None,
+        Err(num_removed_before) => Some(idx - num_removed_before),
+    }
+}
+
+
+// Removes the items at the given indices (sorted) while keeping the order of the rest
+fn remove_indices<T>(items: &mut Vec<T>, removed: &[usize]) {
+    let mut idx = 0;
+    items.retain(|_| {
+        idx += 1;
+        removed.binary_search(&(idx - 1)).is_err()
+    });
+}
+
+
+// The points of a RemovePoints command that exist, sorted and without duplicates
+fn points_to_remove(mut points: Vec<usize>, num_points: usize) -> Vec<usize> {
+    points.sort_unstable();
+    points.dedup();
+    points.retain(|idx| *idx < num_points);
+    points
+}
+
+
+// The links of a CutLinks command that still exist, sorted and without duplicates
+fn links_to_cut(links: &[Link], link_refs: &[LinkRef]) -> Vec<usize> {
+    let mut cut = link_refs.iter().filter_map(|link_ref| link_ref.find(links)).collect::<Vec<_>>();
+    cut.sort_unstable();
+    cut.dedup();
+    cut
+}
+
+
+// Indices of the links attached to any of the removed points (sorted)
+fn attached_links(links: &[Link], removed: &[usize]) -> Vec<usize> {
+    let is_removed = |point_idx: &usize| removed.binary_search(point_idx).is_ok();
+    links.iter().enumerate()
+        .filter(|(_, link)| is_removed(&link.from_idx) || is_removed(&link.to_idx))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+
+// Moves the points of the links to their indices after the removed points (sorted) are gone.
+// None of the links can be attached to a removed point anymore
+fn shift_link_points(links: &mut [Link], removed: &[usize]) {
+    let shift = |point_idx: usize| point_idx - removed.partition_point(|idx| *idx < point_idx);
+    for link in links.iter_mut() {
+        link.from_idx = shift(link.from_idx);
+        link.to_idx = shift(link.to_idx);
     }
 }
 
 
 
+// What the left mouse button does
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum SelectTarget {
-    Point,
-    Link
+pub enum Tool {
+    // Select points and links to edit them, the selection can be dragged directly
+    Select,
+    // Pull points towards the mouse with a spring, so they can be thrown
+    Grab,
+    // Set off an explosion where the mouse is clicked
+    Explode,
+    // Click empty space to add a point, drag from a point to add a link
+    Build,
+    // Drag to draw a rope
+    Rope,
+    // Drag a rectangle to fill it with cloth
+    Cloth,
+}
+
+
+// Where the state lives and gets stepped
+#[derive(Debug)]
+enum Backend {
+    // Stepped inside of Simulation::update, in sync with the frames
+    Local(Box<SimulationState>),
+    // Stepped on its own thread at a fixed rate, see thread::SimulationThread
+    Threaded(SimulationThread),
+    // Only there while switching between the two
+    Moving,
 }
-type Selection = Option<(SelectTarget, usize)>;
 
 
 #[derive(Debug)]
 pub struct Simulation {
-    // Only ever read from previous_state
-    previous_state: SimulationState,
-    // And write to next_state
-    next_state: SimulationState,
+    backend: Backend,
+    // Edits for the local state, waiting for the next batch of steps
+    commands: Vec<Command>,
+    // What gets drawn and edited, while the next steps are computed on the state
+    snapshot: RenderSnapshot,
+    // Speeds up the queries on the snapshot, rebuilt at the end of every update
+    grid: SpatialGrid,
     selection: Selection,
-    dragging: bool,
+    select_gesture: SelectGesture,
+    pub select_shape: SelectShape,
+    pub tool: Tool,
+    pub grab_settings: GrabSettings,
+    grabbed: Vec<GrabTarget>,
+    // Used by the explode tool, the center gets replaced by the mouse position
+    pub explosion: Explosion,
+    pub build_settings: BuildSettings,
+    build_gesture: BuildGesture,
+    // World area that the points are kept in, see set_bounds
+    bounds: Vec2,
+    // Acceleration on every point, see set_force
+    force: Vec2,
+    pub camera: Camera,
+    // What the tools read from the mouse and keyboard this frame
+    input: InputState,
+    pub debug: DebugOverlays,
+    profiler: Profiler,
+    energy: EnergyMonitor,
+    // Number of points once all commands are applied, so new links can refer to points that were just added
+    num_points: usize,
+    // Commands since the last frame that add or remove links, so new IK chains can be checked
+    // against the links they will find
+    link_edits: Vec<Command>,
+    // Result of the last scene export, shown in the tool window
+    export_status: String,
     pub paused: bool,
+    /// Keep measuring the energy for `energy()`. Without it, the energy only gets measured
+    /// while the energy charts are shown or while the guard checks it, see GuardSettings::max_energy_growth
+    pub measure_energy: bool,
+    // Last values sent to the state, to only send commands when they change
+    sent_paused: bool,
+    sent_measure_energy: bool,
+    sent_ik_target: Vec2,
+    sent_guard: GuardSettings,
     frame: i32,
+    /// What happens when the simulation explodes
+    pub guard: GuardSettings,
+    // Shown in the debug window
+    last_instability: Option<InstabilityEvent>,
+    // Whether a replay is being recorded, and whether the tool window should save it once it is done
+    recording: bool,
+    save_recording: bool,
+    // Finished recording, see take_recording
+    recorded: Option<Replay>,
+    divergence: Option<Divergence>,
 
-    color_picker_texture: Texture2D,
-    ui_text_stiffness: String,
+    // Made once the first color picker is shown, so the simulation also works without a window
+    color_picker_texture: Option<Texture2D>,
+    inspector: Inspector,
+}
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Simulation {
     const UPDATE_STEPS: usize = 4;
     const USE_MULTITHREADING: bool = true;
-    const MAX_VELOCITY: f32 = 15.0;
-    const MOTION_DAMPENING: f32 = 0.999;
-    const MAX_LINK_STRESS: f32 = 3.0;
+    // Solve points and links with rayon. Threads arent available on the web
+    const PARALLEL_SOLVER: bool = Simulation::USE_MULTITHREADING && !cfg!(target_arch = "wasm32");
+    // Fastest the points can move (m/s)
+    const MAX_VELOCITY: f32 = 30.0;
+    // Part of the velocity that is left after one second, so it doesnt depend on the step size
+    const MOTION_DAMPENING: f32 = 0.9;
+    // Default load (N) that breaks a link
+    const MAX_LINK_STRESS: f32 = 1000.0;
+    // Furthest a link moves its points in one step (m)
+    const MAX_LINK_OFFSET: f32 = 1.0;
+    // Islands whose points all move slower than SLEEP_VELOCITY (m/s) for SLEEP_TIME seconds fall asleep
+    const USE_SLEEPING: bool = true;
+    const SLEEP_VELOCITY: f32 = 0.1;
+    const SLEEP_TIME: f32 = 1.0;
+    const GRAVITY: Vec2 = Vec2::new(0.0, 9.81);
+    // World area (m) of a new simulation, the size of the default window at the default scale
+    const DEFAULT_BOUNDS: Vec2 = Vec2::new(19.2, 10.8);
+    // Step rate of the simulation thread, matches UPDATE_STEPS at 60 fps
+    const THREAD_STEPS_PER_SECOND: f32 = Simulation::UPDATE_STEPS as f32 * 60.0;
+    // Where the export button saves the scene to
+    #[cfg(not(target_arch = "wasm32"))]
+    const SCENE_EXPORT_PATH: &'static str = "scene.txt";
+    // Where the replay buttons save and load the replay
+    #[cfg(not(target_arch = "wasm32"))]
+    const REPLAY_PATH: &'static str = "replay.txt";
+    #[cfg(not(target_arch = "wasm32"))]
+    const SVG_EXPORT_PATH: &'static str = "frame.svg";
 
     pub fn new() -> Self {
-        let (color_picker_texture, _) = super::ui::color_picker_texture(100, 100);
         Self {
-            previous_state: SimulationState::new(),
-            next_state: SimulationState::new(),
-            selection: None,
-            dragging: false,
+            backend: Backend::Local(Box::new(SimulationState::new())),
+            commands: vec![],
+            snapshot: RenderSnapshot::default(),
+            grid: SpatialGrid::default(),
+            selection: Selection::default(),
+            select_gesture: SelectGesture::None,
+            select_shape: SelectShape::Box,
+            tool: Tool::Select,
+            grab_settings: GrabSettings::default(),
+            grabbed: vec![],
+            explosion: Explosion::new(Vec2::ZERO, 1.5, 15.0).break_links(Simulation::MAX_LINK_STRESS),
+            build_settings: BuildSettings::default(),
+            build_gesture: BuildGesture::None,
+            bounds: Simulation::DEFAULT_BOUNDS,
+            force: Simulation::GRAVITY,
+            camera: Camera::new(Simulation::DEFAULT_BOUNDS * 0.5, 1.0),
+            input: InputState::default(),
+            debug: DebugOverlays::default(),
+            profiler: Profiler::default(),
+            energy: EnergyMonitor::default(),
+            num_points: 0,
+            link_edits: vec![],
+            export_status: String::new(),
             paused: false,
+            measure_energy: false,
+            sent_paused: false,
+            sent_measure_energy: false,
+            sent_ik_target: Vec2::ZERO,
+            sent_guard: GuardSettings::default(),
             frame: 0,
+            guard: GuardSettings::default(),
+            last_instability: None,
+            recording: false,
+            save_recording: false,
+            recorded: None,
+            divergence: None,
 
-            color_picker_texture,
-            ui_text_stiffness: String::new()
+            color_picker_texture: None,
+            inspector: Inspector::default(),
         }
     }
 
 
+    /// Moves the stepping onto its own thread, which steps at a fixed rate independent of the frame rate.
+    /// `delta` is the time of one step, the thread does 240 steps per second.
+    /// Does nothing on the web, since there are no threads.
+    pub fn run_in_thread(&mut self, delta: f32) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        if let Backend::Local(mut state) = std::mem::replace(&mut self.backend, Backend::Moving) {
+            Simulation::apply_commands(&mut state, &mut self.commands);
+            self.backend = Backend::Threaded(SimulationThread::spawn(*state, delta, Simulation::THREAD_STEPS_PER_SECOND));
+            self.push_command(Command::SetPaused(self.paused));
+        }
+    }
+
+
+    /// Queues an edit of the simulation, see `Command` for when it gets applied
+    pub fn push_command(&mut self, command: Command) {
+        if matches!(command, Command::AddPoints(_) | Command::RemovePoints(_) | Command::AddLink(_) | Command::CutLinks(_)) {
+            self.link_edits.push(command.clone());
+        }
+        match &mut self.backend {
+            Backend::Local(_) => self.commands.push(command),
+            Backend::Threaded(thread) => thread.send(command),
+            Backend::Moving => unreachable!(),
+        }
+    }
+
+
+    fn apply_commands(state: &mut SimulationState, commands: &mut Vec<Command>) {
+        for command in commands.drain(..) {
+            state.apply_command(command);
+        }
+    }
+
+
+    /// Reference to a link of the last drawn frame, for use in commands
+    pub fn link_ref(&self, link_idx: usize) -> Option<LinkRef> {
+        self.snapshot.links.get(link_idx).map(|link| LinkRef::new(link_idx, link))
+    }
+
+
     pub fn add_point(&mut self, point: Point) {
         self.add_points(&[point])
     }
 
 
     pub fn add_points(&mut self, points: &[Point]) {
-        for point in points {
-            self.next_state.positions.push(point.position);
-            self.next_state.prev_positions.push(point.position);
-            self.next_state.masses.push(point.mass);
-            self.next_state.colors.push(point.color);
-            self.next_state.fixed.push(point.fixed);
+        self.num_points += points.len();
+        self.push_command(Command::AddPoints(points.to_vec()));
+    }
+
 
-            self.previous_state.positions.push(point.position);
-            self.previous_state.prev_positions.push(point.position);
-            self.previous_state.masses.push(point.mass);
-            self.previous_state.colors.push(point.color);
-            self.previous_state.fixed.push(point.fixed);
+    /// Removes the points together with the links attached to them.
+    /// The indices of the points (and links) after them shift down
+    pub fn remove_points(&mut self, points: &[usize]) {
+        let mut removed = points.iter().copied().filter(|idx| *idx < self.num_points).collect::<Vec<_>>();
+        removed.sort_unstable();
+        removed.dedup();
+        if removed.is_empty() {
+            return;
+        }
+        self.num_points -= removed.len();
+        self.push_command(Command::RemovePoints(removed));
+    }
+
+
+    /// Points and links of the last drawn frame
+    pub fn export_scene(&self) -> Scene {
+        let snapshot = &self.snapshot;
+        let points = (0..snapshot.positions.len()).map(|i| {
+            let point = Point::new(snapshot.positions[i]).mass(snapshot.masses[i]).color(snapshot.colors[i]);
+            if snapshot.fixed[i] { point.fixed() } else { point }
+        }).collect();
+        Scene {
+            points,
+            links: snapshot.links.clone(),
+            bounds: Some(self.bounds),
+            pixels_per_meter: Some(self.camera.pixels_per_meter),
+        }
+    }
+
+
+    /// Adds the points and links of the scene to the ones already there, and takes its bounds and scale if it has any
+    pub fn load_scene(&mut self, scene: &Scene) {
+        if let Some(pixels_per_meter) = scene.pixels_per_meter {
+            self.camera.pixels_per_meter = pixels_per_meter;
+        }
+        if let Some(bounds) = scene.bounds {
+            self.set_bounds(bounds);
+            self.camera.fit(bounds);
+        }
+        let first_idx = self.num_points;
+        self.add_points(&scene.points);
+        for link in scene.links.iter() {
+            let mut link = link.clone();
+            link.from_idx += first_idx;
+            link.to_idx += first_idx;
+            self.add_link(link);
         }
     }
 
 
     pub fn add_link(&mut self, link: Link) {
-        self.next_state.links.push(link.clone());
-        self.previous_state.links.push(link.clone());
+        self.push_command(Command::AddLink(link));
     }
 
 
-    pub fn add_ik_chain(&mut self, mut ik_chain: IKChain) {
-        ik_chain.current_max_length = ik_chain.links.iter().map(|link| {
-            let link = &self.previous_state.links[*link];
-            link.max_length
-        }).sum::<f32>();
-        self.next_state.ik_chains.push(ik_chain.clone());
-        self.previous_state.ik_chains.push(ik_chain);
+    pub fn add_ik_chain(&mut self, ik_chain: IKChain) {
+        self.push_command(Command::AddIKChain(ik_chain));
+    }
+
+
+    /// Applies an instantaneous impulse (kg*m/s) to a point in the next step
+    pub fn apply_impulse(&mut self, point_idx: usize, impulse: Vec2) {
+        self.push_command(Command::ApplyImpulse(point_idx, impulse));
     }
 
 
-    // User input, that isnt selection
-    pub fn handle_interaction(&mut self) {
-        let mouse_pos = Vec2::from(mouse_position());
-        let prev_mouse_pos = mouse_pos - mouse_delta_position() * Vec2::from(screen_size());
+    /// Applies the impulse of the explosion to every point inside of its radius
+    /// and breaks the links that it loads with more than its break threshold (N)
+    pub fn explode(&mut self, explosion: Explosion) {
+        self.push_command(Command::Explode(explosion));
+    }
+
+
+    /// Positions (m) of the points in the last drawn frame
+    pub fn positions(&self) -> &[Vec2] {
+        &self.snapshot.positions
+    }
+
+
+    /// Strain of every link in the last drawn frame, see Link::strain
+    pub fn link_strains(&self) -> impl Iterator<Item = f32> + '_ {
+        let positions = &self.snapshot.positions;
+        self.snapshot.links.iter().map(|link| link.strain(positions[link.from_idx].distance(positions[link.to_idx])))
+    }
+
+
+    /// Connected groups of points (through links) in the last drawn frame
+    pub fn components(&self) -> Vec<Component> {
+        self.snapshot.components()
+    }
+
+
+    /// Id of the component that the point belongs to
+    pub fn component_of(&self, point_idx: usize) -> Option<u64> {
+        self.snapshot.islands.point_island.get(point_idx).map(|island_idx| self.snapshot.islands.islands[*island_idx].id)
+    }
+
+
+    /// Components that fell apart during the last update
+    pub fn body_splits(&self) -> &[BodySplit] {
+        &self.snapshot.body_splits
+    }
+
+
+    /// Closest point that is at most max_distance away from the position
+    pub fn nearest_point(&self, position: Vec2, max_distance: f32) -> Option<usize> {
+        self.grid.nearest_point(&self.snapshot.positions, position, max_distance, |_| true)
+    }
+
+
+    /// Closest link that is at most max_distance away from the position
+    pub fn nearest_link(&self, position: Vec2, max_distance: f32) -> Option<usize> {
+        self.grid.nearest_link(&self.snapshot, position, max_distance)
+    }
+
+
+    /// Points that are at most radius away from the center, sorted by index
+    pub fn points_in_radius(&self, center: Vec2, radius: f32) -> Vec<usize> {
+        self.grid.points_in_radius(&self.snapshot.positions, center, radius)
+    }
+
+
+    /// Points inside of the rectangle, sorted by index
+    pub fn points_in_rect(&self, min: Vec2, max: Vec2) -> Vec<usize> {
+        self.grid.points_in_rect(&self.snapshot.positions, min, max)
+    }
+
+
+    /// Links that are at least partly inside of the rectangle, sorted by index
+    pub fn links_in_rect(&self, min: Vec2, max: Vec2) -> Vec<usize> {
+        self.grid.links_in_rect(&self.snapshot, min, max)
+    }
+
+
+    /// First link or wall of the bounds that the ray hits within max_distance
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        let link_hit = self.grid.raycast_links(&self.snapshot, origin, direction, max_distance);
+        let max_distance = link_hit.map_or(max_distance, |hit| hit.distance);
+        query::raycast_bounds(origin, direction, max_distance, self.bounds)
+            .filter(|hit| link_hit.is_none_or(|link_hit| hit.distance < link_hit.distance))
+            .or(link_hit)
+    }
+
+
+    /// Sets the acceleration (m/s²) that acts on every point, gravity by default
+    pub fn set_force(&mut self, force: Vec2) {
+        self.force = force;
+        self.push_command(Command::SetForce(force));
+    }
+
+
+    /// World area that the points are kept in, from (0, 0) to bounds
+    pub fn bounds(&self) -> Vec2 {
+        self.bounds
+    }
+
+
+    /// Sets the world area that the points are kept in, from (0, 0) to bounds
+    pub fn set_bounds(&mut self, bounds: Vec2) {
+        self.bounds = bounds;
+        self.push_command(Command::SetBounds(bounds));
+    }
+
+
+    /// Runs the tools with the input of one frame. Only queues commands and doesnt draw anything,
+    /// the previews of the tools are drawn by update
+    pub fn handle_input(&mut self, input: InputState) {
+        self.input = input;
+        if self.input.is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+        self.handle_camera();
+        self.handle_select_gestures();
+        self.handle_build_tools();
+        self.handle_grab();
+        self.handle_explode_tool();
+        self.handle_interaction();
+    }
+
+
+    // Cuts the links that the mouse swipes across with the right button, and moves the IK target to the mouse
+    fn handle_interaction(&mut self) {
+        let mouse_pos = self.mouse_position();
+        let prev_mouse_pos = self.camera.screen_to_world(self.input.prev_mouse_position);
         let middle_mouse_pos = (mouse_pos + prev_mouse_pos) * 0.5;
-        let is_dragging = is_mouse_button_down(MouseButton::Right) && mouse_delta_position().length() > 0.0;
+        let is_dragging = self.input.is_down(MouseButton::Right) && self.input.mouse_moved();
 
-        let mut new_links = if self.paused {
-            self.next_state.links.clone()
-        } else {
-            self.previous_state.links.clone()
-        };
-        let mut already_removed_links = 0;
-        new_links = new_links.into_iter().enumerate().filter_map(|(i, link)| {
-            if self.next_state.removed_link_indices.binary_search(&i).is_ok() {
-                already_removed_links += 1;
-                return None;
-            }
-            let p0 = self.next_state.positions[self.next_state.links[i - already_removed_links].from_idx];
-            let p1 = self.next_state.positions[self.next_state.links[i - already_removed_links].to_idx];
-            
-            let side_of_mouse_pos = side_of_line(mouse_pos, p0, p1);
-            let side_of_prev_mouse_pos = side_of_line(prev_mouse_pos, p0, p1);
-            let length_of_link = p0.distance(p1);
-            if is_dragging && side_of_mouse_pos != side_of_prev_mouse_pos && middle_mouse_pos.distance((p1 + p0) * 0.5) < length_of_link * 0.5 {
-                self.next_state.removed_link_indices.push(i);
-                None
-            } else {
-                Some(link)
+        if is_dragging {
+            let positions = &self.snapshot.positions;
+            let cut_links = self.snapshot.links.iter().enumerate().filter(|(_, link)| {
+                let p0 = positions[link.from_idx];
+                let p1 = positions[link.to_idx];
+                
+                let side_of_mouse_pos = side_of_line(mouse_pos, p0, p1);
+                let side_of_prev_mouse_pos = side_of_line(prev_mouse_pos, p0, p1);
+                let length_of_link = p0.distance(p1);
+                side_of_mouse_pos != side_of_prev_mouse_pos && middle_mouse_pos.distance((p1 + p0) * 0.5) < length_of_link * 0.5
+            }).map(|(i, link)| LinkRef::new(i, link)).collect::<Vec<_>>();
+            if !cut_links.is_empty() {
+                self.push_command(Command::CutLinks(cut_links));
             }
-        }).collect();
-        self.next_state.links = new_links;
+        }
 
-        if !self.next_state.ik_chains.is_empty() {
-            self.next_state.ik_chains[0].target_position = mouse_pos;
+        if mouse_pos != self.sent_ik_target {
+            self.sent_ik_target = mouse_pos;
+            self.push_command(Command::SetIKTarget(0, mouse_pos));
         }
     }
 
 
-    pub fn handle_selection(&mut self) {
-        let mouse_pos = mouse_position();
-        let mouse_pos = Vec2::new(mouse_pos.0, mouse_pos.1).clamp(Vec2::ZERO, Vec2::from(screen_size()));
-        let mouse_over_ui = ui::root_ui().is_mouse_over(mouse_pos);
+    fn draw_inspectors(&mut self) {
+        if let Some(point_idx) = self.selection.single_point() {
+            self.draw_point_inspector(point_idx);
+        } else if let Some(link_idx) = self.selection.single_link() {
+            self.draw_link_inspector(link_idx);
+        } else if !self.selection.is_empty() {
+            self.draw_group_inspector();
+        }
+    }
 
-        if is_mouse_button_pressed(MouseButton::Left) && !mouse_over_ui { // Find a point to select
-            self.selection = None;
-            let mut selection_distance = f32::MAX;
-            for i in 0..self.next_state.positions.len() {
-                let pos = self.next_state.positions[i];
-                let dist = mouse_pos.distance(pos);
-                if dist < POINT_RADIUS {
-                    self.selection = Some((SelectTarget::Point, i));
-                    selection_distance = dist - POINT_RADIUS;
-                }
-            }
-            for i in 0..self.next_state.links.len() {
-                let link = &self.next_state.links[i];
-                let dist = distance_from_line(mouse_pos, self.next_state.positions[link.from_idx], self.next_state.positions[link.to_idx]);
-                if dist + POINT_RADIUS < POINT_RADIUS*SELECT_GRACE && dist < selection_distance {
-                    self.selection = Some((SelectTarget::Link, i));
-                    self.ui_text_stiffness = self.next_state.links[i].stiffness.to_string();
-                    selection_distance = dist;
-                }
+
+    // Texture of the color pickers, made the first time one is shown
+    fn color_picker_texture(&mut self) -> Texture2D {
+        self.color_picker_texture.get_or_insert_with(|| super::ui::color_picker_texture(100, 100).0).clone()
+    }
+
+
+    fn handle_grab(&mut self) {
+        let mouse_pos = self.mouse_position();
+        if !self.input.is_down(MouseButton::Left) || self.tool != Tool::Grab {
+            // Releasing just stops pulling, the points keep the velocity they had
+            if !self.grabbed.is_empty() {
+                self.grabbed.clear();
+                self.push_command(Command::SetGrab(vec![], 0.0));
             }
+            return;
+        }
+        if self.input.is_pressed(MouseButton::Left) && !self.input.mouse_over_ui {
+            self.grabbed = grab::find_grab_targets(&self.snapshot, &self.grid, mouse_pos, &self.grab_settings);
+        }
+        if self.grabbed.is_empty() {
+            return;
+        }
+        for target in self.grabbed.iter_mut() {
+            target.follow(mouse_pos);
+        }
+        self.push_command(Command::SetGrab(self.grabbed.clone(), self.grab_settings.strength));
+    }
+
+
+    fn handle_explode_tool(&mut self) {
+        if self.tool != Tool::Explode {
+            return;
+        }
+        if self.input.is_pressed(MouseButton::Left) && !self.input.mouse_over_ui {
+            self.explode(self.explosion.center(self.mouse_position()));
         }
+    }
 
-        
-        if let Some(target) = &self.selection {
-            if target.0 == SelectTarget::Point {
-                ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(200.0, 200.0))
-                    .label(&format!("Editing Point {}", target.1))
-                    .movable(false)
-                    .ui(&mut ui::root_ui(), |ui| {
-                        colorbox(
-                            ui,
-                            hash!(),
-                            "Start color",
-                            &mut self.next_state.colors[target.1],
-                            self.color_picker_texture.clone(),
-                        );
-                        ui.checkbox(hash!(), "Fixed", &mut self.next_state.fixed[target.1])
-                });
-
-                if !mouse_over_ui {
-                    if is_mouse_button_down(MouseButton::Left) && mouse_delta_position().length() > 0.0 {
-                        self.dragging = true;
-                    } else if !is_mouse_button_down(MouseButton::Left) {
-                        self.dragging = false;
+
+    // Pans, zooms and follows
+    fn handle_camera(&mut self) {
+        self.camera.handle_input(&self.input);
+        if self.input.is_key_pressed(KeyCode::F) {
+            self.camera.follow = match self.camera.follow {
+                Some(_) => None,
+                None => self.selection.single_point(),
+            };
+        }
+        if let Some(pos) = self.camera.follow.and_then(|idx| self.snapshot.positions.get(idx)) {
+            self.camera.center = *pos;
+        }
+    }
+
+
+    // What the tools are about to do
+    fn draw_tool_previews(&self, renderer: &mut impl Renderer) {
+        let mouse_pos = self.mouse_position();
+        self.draw_select_preview(renderer, mouse_pos);
+        self.draw_build_preview(renderer, mouse_pos);
+        for target in self.grabbed.iter() {
+            renderer.line(self.snapshot.positions[target.point_idx], target.target, self.camera.pixels(1.0), SELECT_COLOR);
+        }
+        if self.tool == Tool::Explode {
+            renderer.circle_lines(mouse_pos, self.explosion.radius, self.camera.pixels(1.0), ORANGE);
+        }
+    }
+
+
+    /// Draws the last frame with the debug overlays and selection, without the ui and tool previews
+    pub fn draw_to(&self, renderer: &mut impl Renderer) {
+        Simulation::draw(&self.snapshot, &self.selection, &self.debug_draw(), renderer);
+    }
+
+
+    /// The last drawn frame as an SVG file, see draw_to
+    pub fn export_svg(&self) -> String {
+        let mut renderer = SvgRenderer::new(&self.camera);
+        self.draw_to(&mut renderer);
+        renderer.finish(BLACK)
+    }
+
+
+    /// The last drawn frame drawn on the CPU, in the screen size of the camera. Works without a window, see draw_to
+    pub fn render_image(&self, background: Color) -> Image {
+        let mut renderer = SoftwareRenderer::new(&self.camera, background);
+        self.draw_to(&mut renderer);
+        renderer.into_image()
+    }
+
+
+    fn debug_draw(&self) -> DebugDraw<'_> {
+        DebugDraw {
+            overlays: self.debug,
+            camera: &self.camera,
+            grid: &self.grid,
+            bounds: self.bounds,
+            force: self.force,
+            step_delta: self.snapshot.step_delta,
+        }
+    }
+
+
+    fn draw_tool_window(&mut self) {
+        ui::widgets::Window::new(hash!(), vec2(screen_width() - 260.0, 10.0), vec2(250.0, 400.0))
+            .label("Tools")
+            .movable(false)
+            .ui(&mut ui::root_ui(), |ui| {
+                ui.label(None, &format!("Current: {:?}", self.tool));
+                ui.label(None, &format!("Pieces: {}", self.snapshot.islands.islands.len()));
+                if ui.button(None, "Select") {
+                    self.tool = Tool::Select;
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Grab") {
+                    self.tool = Tool::Grab;
+                    self.selection.clear();
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Explode") {
+                    self.tool = Tool::Explode;
+                    self.selection.clear();
+                }
+                if ui.button(None, "Build") {
+                    self.tool = Tool::Build;
+                    self.selection.clear();
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Rope") {
+                    self.tool = Tool::Rope;
+                    self.selection.clear();
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Cloth") {
+                    self.tool = Tool::Cloth;
+                    self.selection.clear();
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button(None, "Export scene") {
+                    self.export_status = match self.export_scene().save(Simulation::SCENE_EXPORT_PATH) {
+                        Ok(()) => format!("Saved to {}", Simulation::SCENE_EXPORT_PATH),
+                        Err(err) => format!("Export failed: {}", err),
+                    };
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button(None, if self.recording { "Stop recording" } else { "Record replay" }) {
+                    if self.recording {
+                        self.save_recording = true;
+                        self.stop_recording();
+                    } else {
+                        self.start_recording(0);
+                        self.export_status = "Recording".to_owned();
                     }
-                    if self.dragging {
-                        self.next_state.positions[target.1] = mouse_pos;
-                        self.next_state.prev_positions[target.1] = mouse_pos;
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button(None, "Play replay") {
+                    match Replay::load(Simulation::REPLAY_PATH) {
+                        Ok(replay) => {
+                            self.recording = false;
+                            self.play_replay(replay);
+                            self.export_status = format!("Playing {}", Simulation::REPLAY_PATH);
+                        },
+                        Err(err) => self.export_status = format!("Loading the replay failed: {}", err),
                     }
                 }
-            } else if target.0 == SelectTarget::Link {
-                ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(200.0, 200.0))
-                    .label(&format!("Editing Link {}", target.1))
-                    .movable(false)
-                    .ui(&mut ui::root_ui(), |ui| {
-                        ui.slider(hash!(), "Min length", 0f32..1000f32, &mut self.next_state.links[target.1].min_length);
-                        ui.slider(hash!(), "Max length", 0f32..1000f32, &mut self.next_state.links[target.1].max_length);
-                        ui.input_text(hash!(), "Stiffness", &mut self.ui_text_stiffness);
-                        
-                        
-                        
-                        // TODO: Maybe pass in "edit_state" into handle_selection and handle_interaction which is either next_state or previous_state depending on self.paused
-                        
-                        
-                        
-                        ui.slider(hash!(), "Damping", 0f32..1f32, &mut self.previous_state.links[target.1].damping);
-                        self.next_state.links[target.1].min_length = self.next_state.links[target.1].min_length.min(self.next_state.links[target.1].max_length);
-
-                        // Clean up input string a bit and parse it back to a float
-                        self.ui_text_stiffness = self.ui_text_stiffness.trim_end().to_string();
-                        if let Ok(val) = self.ui_text_stiffness.parse::<f32>() {
-                            self.next_state.links[target.1].stiffness = val;
-                        };
-                });
-            }
-        };
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button(None, "Export SVG") {
+                    self.export_status = match std::fs::write(Simulation::SVG_EXPORT_PATH, self.export_svg()) {
+                        Ok(()) => format!("Saved to {}", Simulation::SVG_EXPORT_PATH),
+                        Err(err) => format!("Export failed: {}", err),
+                    };
+                }
+                if !self.export_status.is_empty() {
+                    ui.label(None, &self.export_status);
+                }
+                ui.label(None, &format!("Zoom: {:.0}%", self.camera.zoom * 100.0));
+                ui.label(None, "Middle mouse: pan, F: follow");
+                if ui.button(None, "Fit bounds") {
+                    self.camera.fit(self.bounds);
+                    self.camera.follow = None;
+                }
+                if let Some(point_idx) = self.camera.follow {
+                    ui.same_line(0.0);
+                    if ui.button(None, format!("Stop following {}", point_idx)) {
+                        self.camera.follow = None;
+                    }
+                }
+                if matches!(self.tool, Tool::Build | Tool::Rope | Tool::Cloth) {
+                    let material = &mut self.build_settings.material;
+                    ui.slider(hash!(), "Stiffness", 0f32..1f32, &mut material.stiffness);
+                    ui.slider(hash!(), "Damping", 0f32..1f32, &mut material.damping);
+                    ui.slider(hash!(), "Mass", 0.1f32..50f32, &mut material.mass);
+                }
+                if matches!(self.tool, Tool::Rope | Tool::Cloth) {
+                    ui.slider(hash!(), "Spacing (m)", 0.05f32..1f32, &mut self.build_settings.spacing);
+                }
+                if self.tool == Tool::Cloth {
+                    ui.checkbox(hash!(), "Pin top row", &mut self.build_settings.pin_top);
+                }
+                if self.tool == Tool::Select {
+                    ui.label(None, "Shift: add, Ctrl: remove, Del: delete");
+                    let mut lasso = self.select_shape == SelectShape::Lasso;
+                    ui.checkbox(hash!(), "Lasso", &mut lasso);
+                    self.select_shape = if lasso { SelectShape::Lasso } else { SelectShape::Box };
+                }
+                if self.tool == Tool::Grab {
+                    ui.slider(hash!(), "Strength", 0f32..1f32, &mut self.grab_settings.strength);
+                    ui.slider(hash!(), "Radius (m)", 0.01f32..3f32, &mut self.grab_settings.radius);
+                    let mut grab_radius = self.grab_settings.mode == GrabMode::Radius;
+                    ui.checkbox(hash!(), "Grab everything in radius", &mut grab_radius);
+                    self.grab_settings.mode = if grab_radius { GrabMode::Radius } else { GrabMode::Nearest };
+                }
+                if self.tool == Tool::Explode {
+                    ui.slider(hash!(), "Strength (kg*m/s)", 0f32..50f32, &mut self.explosion.strength);
+                    ui.slider(hash!(), "Radius (m)", 0.01f32..5f32, &mut self.explosion.radius);
+                    let falloffs = [Falloff::Constant, Falloff::Linear, Falloff::Quadratic];
+                    let mut falloff_idx = falloffs.iter().position(|f| *f == self.explosion.falloff).unwrap_or(0);
+                    ui.combo_box(hash!(), "Falloff", &["Constant", "Linear", "Quadratic"], &mut falloff_idx);
+                    self.explosion.falloff = falloffs[falloff_idx];
+                    let mut break_links = self.explosion.break_threshold.is_some();
+                    ui.checkbox(hash!(), "Break links", &mut break_links);
+                    if break_links {
+                        let mut threshold = self.explosion.break_threshold.unwrap_or(Simulation::MAX_LINK_STRESS);
+                        ui.slider(hash!(), "Break load (N)", 0f32..5000f32, &mut threshold);
+                        self.explosion.break_threshold = Some(threshold);
+                    } else {
+                        self.explosion.break_threshold = None;
+                    }
+                    let mut directional = self.explosion.direction.is_some();
+                    ui.checkbox(hash!(), "Directional", &mut directional);
+                    if directional {
+                        let mut angle = self.explosion.direction.map_or(-90.0, |dir| dir.y.atan2(dir.x).to_degrees());
+                        ui.slider(hash!(), "Angle", -180f32..180f32, &mut angle);
+                        self.explosion.direction = Some(Vec2::from_angle(angle.to_radians()));
+                    } else {
+                        self.explosion.direction = None;
+                    }
+                }
+        });
     }
 
 
+    /// Handles the input, advances the simulation by delta seconds and draws it
     pub fn update(&mut self, delta: f32) {
-        self.frame += 1;
-        if is_key_pressed(KeyCode::Space) {
-            self.paused = !self.paused;
-            // Copy over the modified next_state if unpaused
-            if !self.paused {
-                let _ = std::mem::replace(&mut self.previous_state, self.next_state.clone());
-            }
-        }
+        let frame_time = self.profiler.begin_frame();
+        let interaction_start = profiler::now();
+        self.begin_frame();
+        self.draw_tool_window();
+        self.draw_debug_window();
+        self.handle_input(InputState::from_macroquad());
+        self.send_settings();
+        set_camera(&self.camera.to_macroquad());
+        self.draw_tool_previews(&mut MacroquadRenderer::new(&self.camera));
+        self.draw_inspectors();
+        let interaction = (profiler::now() - interaction_start) as f32;
 
-        self.handle_selection();
-        self.handle_interaction();
-
-        if Simulation::USE_MULTITHREADING && !cfg!(target_arch="wasm32") {
-            rayon::in_place_scope(|s| {
-                if !self.paused {
-                    let prev_draw = self.previous_state.clone();
-                    s.spawn(|_| {
-                        for _ in 0..Simulation::UPDATE_STEPS {
-                            Simulation::update_state(&mut self.next_state, &self.previous_state, delta);
-                            std::mem::swap(&mut self.next_state, &mut self.previous_state);
-                        }
+        let mut draw = 0.0;
+        let mut renderer = MacroquadRenderer::new(&self.camera);
+        // Out of self while stepping, so the draws can borrow the rest of it
+        let mut backend = std::mem::replace(&mut self.backend, Backend::Moving);
+        match &mut backend {
+            Backend::Local(state) => {
+                // This is the one point where the queued edits get applied to the local state
+                Simulation::apply_commands(state, &mut self.commands);
+                if self.paused {
+                    self.snapshot.update(state);
+                    draw = profiler::time(|| Simulation::draw(&self.snapshot, &self.selection, &self.debug_draw(), &mut renderer));
+                } else if Simulation::USE_MULTITHREADING && !cfg!(target_arch="wasm32") {
+                    // Draw the last snapshot while the next steps are being computed
+                    rayon::in_place_scope(|s| {
+                        s.spawn(|_| Simulation::step(state, delta));
+                        draw = profiler::time(|| Simulation::draw(&self.snapshot, &self.selection, &self.debug_draw(), &mut renderer));
                     });
-                    Simulation::draw(&prev_draw, &self.selection);
+                    self.snapshot.update(state);
                 } else {
-                    Simulation::draw(&self.next_state, &self.selection);
+                    Simulation::step(state, delta);
+                    draw = profiler::time(|| Simulation::draw(&self.snapshot, &self.selection, &self.debug_draw(), &mut renderer));
+                    self.snapshot.update(state);
                 }
-            });
-        } else {
-            if !self.paused {
-                for _ in 0..Simulation::UPDATE_STEPS {
-                    Simulation::update_state(&mut self.next_state, &self.previous_state, delta);
-                    std::mem::swap(&mut self.next_state, &mut self.previous_state);
+            },
+            Backend::Threaded(thread) => {
+                thread.receive(&mut self.snapshot);
+                draw = profiler::time(|| Simulation::draw(&self.snapshot, &self.selection, &self.debug_draw(), &mut renderer));
+            },
+            Backend::Moving => unreachable!(),
+        }
+        self.backend = backend;
+        set_default_camera();
+        self.end_frame();
+
+        let timings = std::mem::take(&mut self.snapshot.step_timings);
+        self.profiler.push(FrameProfile {
+            frame_time,
+            integration: timings.integration,
+            fabrik: timings.fabrik,
+            constraints: timings.constraints,
+            interaction,
+            draw,
+            substeps: timings.substeps,
+            breaks: timings.breaks,
+            points: self.snapshot.positions.len(),
+            links: self.snapshot.links.len(),
+            ik_chains: self.snapshot.ik_chains.len(),
+        });
+        if self.debug.profiler {
+            self.profiler.draw_hud();
+        }
+        if self.debug.energy {
+            self.energy.draw_charts();
+        }
+    }
+
+
+    /// Like update, but without drawing and without reading anything from macroquad, so it also works without a window.
+    /// The tools get the input instead of the mouse and keyboard
+    pub fn update_headless(&mut self, delta: f32, input: InputState) {
+        self.run_headless(input, |state| Simulation::step(state, delta));
+    }
+
+
+    /// A single step of `delta` seconds without any input, where update_headless does UPDATE_STEPS of them.
+    /// For batch runs that look at every step
+    pub fn step_headless(&mut self, delta: f32) {
+        let input = InputState::default().screen_size(self.camera.screen_size);
+        self.run_headless(input, |state| Simulation::update_state(state, delta));
+    }
+
+
+    // One frame of update_headless, with `step` stepping the local state
+    fn run_headless(&mut self, input: InputState, step: impl FnOnce(&mut SimulationState)) {
+        self.begin_frame();
+        self.handle_input(input);
+        self.send_settings();
+        match &mut self.backend {
+            Backend::Local(state) => {
+                Simulation::apply_commands(state, &mut self.commands);
+                if !self.paused {
+                    step(state);
                 }
+                self.snapshot.update(state);
+            },
+            Backend::Threaded(thread) => thread.receive(&mut self.snapshot),
+            Backend::Moving => unreachable!(),
+        }
+        self.end_frame();
+        self.snapshot.step_timings = StepTimings::default();
+    }
+
+
+    fn begin_frame(&mut self) {
+        self.frame += 1;
+        self.snapshot.body_splits.clear();
+        self.snapshot.instabilities.clear();
+        self.snapshot.link_breaks.clear();
+    }
+
+
+    // Sends the settings that changed since the last frame
+    fn send_settings(&mut self) {
+        if self.paused != self.sent_paused {
+            self.sent_paused = self.paused;
+            self.push_command(Command::SetPaused(self.paused));
+        }
+        if self.guard != self.sent_guard {
+            self.sent_guard = self.guard;
+            self.push_command(Command::SetGuard(self.guard));
+        }
+        let measure_energy = self.measure_energy || self.debug.energy;
+        if measure_energy != self.sent_measure_energy {
+            self.sent_measure_energy = measure_energy;
+            self.push_command(Command::SetMeasureEnergy(measure_energy));
+        }
+    }
+
+
+    // Catches up with the new snapshot
+    fn end_frame(&mut self) {
+        self.link_edits.clear();
+        self.grid.rebuild(&self.snapshot);
+        self.fix_selection();
+        if let Some(event) = self.snapshot.instabilities.last() {
+            self.last_instability = Some(event.clone());
+        }
+        self.handle_replay_events();
+        self.energy.extend(self.snapshot.energy_samples.drain(..));
+    }
+
+
+    /// Instabilities that the guard found during the last update, see GuardSettings
+    pub fn instabilities(&self) -> &[InstabilityEvent] {
+        &self.snapshot.instabilities
+    }
+
+
+    /// Links that broke during the last update
+    pub fn link_breaks(&self) -> &[LinkBreak] {
+        &self.snapshot.link_breaks
+    }
+
+
+    /// Steps every step with `delta` seconds and without threads, so the same commands always give the same positions,
+    /// no matter the frame rate. None goes back to normal. Does nothing while recording or playing a replay
+    pub fn set_deterministic(&mut self, delta: Option<f32>) {
+        self.push_command(Command::SetDeterministic(delta));
+    }
+
+
+    /// Starts recording everything that happens into a replay, in deterministic mode. The seed gets saved with it
+    pub fn start_recording(&mut self, seed: u64) {
+        self.recording = true;
+        self.push_command(Command::StartRecording(seed));
+    }
+
+
+    /// The replay shows up in take_recording once the simulation got the command
+    pub fn stop_recording(&mut self) {
+        self.recording = false;
+        self.push_command(Command::StopRecording);
+    }
+
+
+    pub fn take_recording(&mut self) -> Option<Replay> {
+        self.recorded.take()
+    }
+
+
+    /// Replaces everything with the start of the replay and plays it back. Edits during the playback make it diverge
+    pub fn play_replay(&mut self, replay: Replay) {
+        self.bounds = replay.bounds();
+        self.force = replay.force();
+        self.guard = replay.guard();
+        self.sent_guard = self.guard;
+        self.num_points = replay.num_points();
+        self.link_edits.clear();
+        self.selection.clear();
+        self.grabbed.clear();
+        self.select_gesture = SelectGesture::None;
+        self.build_gesture = BuildGesture::None;
+        self.camera.follow = None;
+        self.divergence = None;
+        self.push_command(Command::PlayReplay(Box::new(replay)));
+    }
+
+
+    /// First step where the last played replay went differently than when it was recorded
+    pub fn replay_divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+
+
+    fn handle_replay_events(&mut self) {
+        for event in std::mem::take(&mut self.snapshot.replay_events) {
+            match event {
+                ReplayEvent::Recorded(replay) => {
+                    if std::mem::take(&mut self.save_recording) {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            self.export_status = match replay.save(Simulation::REPLAY_PATH) {
+                                Ok(()) => format!("Saved to {}", Simulation::REPLAY_PATH),
+                                Err(err) => format!("Saving the replay failed: {}", err),
+                            };
+                        }
+                    }
+                    self.recorded = Some(*replay);
+                },
+                ReplayEvent::Diverged(divergence) => {
+                    self.export_status = format!("Replay {}", divergence);
+                    self.divergence = Some(divergence);
+                },
+                ReplayEvent::Finished => {
+                    if self.divergence.is_none() {
+                        self.export_status = "Replay finished without diverging".to_owned();
+                    }
+                },
             }
-            Simulation::draw(&self.next_state, &self.selection);
         }
+    }
 
-        
-        self.next_state.removed_link_indices.clear();
-        if !self.paused {
-            std::mem::swap(&mut self.next_state, &mut self.previous_state);
+
+    /// Frame times and counts of the last frames
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+
+    /// Energy and momentum of the last steps
+    pub fn energy(&self) -> &EnergyMonitor {
+        &self.energy
+    }
+
+
+    // Splits the time into UPDATE_STEPS steps
+    fn step(state: &mut SimulationState, delta: f32) {
+        let delta = delta / Simulation::UPDATE_STEPS as f32;
+        for _ in 0..Simulation::UPDATE_STEPS {
+            Simulation::update_state(state, delta);
+        }
+    }
+
+
+    // Points and links might have been removed, so the selected indices could be wrong now
+    fn fix_selection(&mut self) {
+        // After a rollback the links are the ones from before it, so the link indices mean something else
+        if self.snapshot.take_reset() {
+            self.selection.links.clear();
+        }
+        for removed in self.snapshot.take_removed_links() {
+            self.selection.remap_links(&removed);
+        }
+        for removed in self.snapshot.take_removed_points() {
+            self.selection.remap_points(&removed);
+            self.grabbed.retain_mut(|target| {
+                remap_index(target.point_idx, &removed).map(|point_idx| target.point_idx = point_idx).is_some()
+            });
+            self.camera.follow = self.camera.follow.and_then(|idx| remap_index(idx, &removed));
+            self.select_gesture = SelectGesture::None;
+            self.build_gesture = BuildGesture::None;
         }
     }
 
 
-    fn update_state(next_state: &mut SimulationState, previous_state: &SimulationState, delta: f32) {
+    // One step, split into as many substeps as the guard wants, followed by the stability check
+    fn update_state(state: &mut SimulationState, delta: f32) {
         if delta > 1.0 {
             return;
         }
 
-        // Somehow macroquad doesnt want to be called inside of a rayon iterator, so call it outside
-        let screen_size = screen_size();
-        for i in 0..next_state.positions.len() {
-            if previous_state.fixed[i] {
-                continue;
+        let delta = state.fixed_delta.unwrap_or(delta);
+        for command in state.take_replay_commands() {
+            state.apply_command(command);
+        }
+
+        let substeps = state.guard.substeps.max(1);
+        let substep_delta = delta / substeps as f32;
+        if substep_delta != state.step_delta {
+            // The velocities are stored as the distance moved in one substep, so they have to be rescaled
+            let scale = substep_delta / state.step_delta.max(f32::EPSILON);
+            for (pos, prev_pos) in state.positions.iter().zip(state.prev_positions.iter_mut()) {
+                *prev_pos = *pos - (*pos - *prev_pos) * scale;
+            }
+            state.step_delta = substep_delta;
+        }
+        for _ in 0..substeps {
+            Simulation::substep(state, substep_delta);
+        }
+        // Only the guard reads the energy inside of the state, the rest goes to the render snapshot
+        if state.measure_energy || state.guard.settings.max_energy_growth.is_finite() {
+            let sample = EnergySample::measure(state, substep_delta);
+            state.energy_samples.push(sample);
+        }
+        state.check_stability();
+        state.steps += 1;
+        state.after_replay_step();
+    }
+
+
+    fn substep(state: &mut SimulationState, delta: f32) {
+        if state.islands.topology_changed {
+            let links = &state.links;
+            let driven_points = state.kinematic_paths.iter().map(|(idx, _)| *idx)
+                .chain(state.ik_chains.iter().flat_map(|chain| chain.links.iter().flat_map(|link_idx| [links[*link_idx].from_idx, links[*link_idx].to_idx])));
+            state.islands.set_driven_points(driven_points);
+            state.islands.topology_changed = false;
+        }
+        for target in state.grabbed.iter() {
+            state.islands.wake_point(target.point_idx);
+        }
+
+        let integration_start = profiler::now();
+        let bounds = state.bounds;
+        let integrate = |(i, (pos, prev_pos)): (usize, (&mut Vec2, &mut Vec2))| {
+            if state.fixed[i] || state.islands.is_asleep(i) {
+                return;
             };
     
-            let mut velocity = previous_state.positions[i] - previous_state.prev_positions[i];
+            let mut velocity = *pos - *prev_pos;
             if velocity.length() > f32::EPSILON {
-                velocity = velocity.clamp_length_max(Simulation::MAX_VELOCITY) * Simulation::MOTION_DAMPENING;
+                velocity = velocity.clamp_length_max(Simulation::MAX_VELOCITY * delta) * Simulation::MOTION_DAMPENING.powf(delta);
             }
-            let mut new_prev_pos = previous_state.positions[i];
+            let mut new_prev_pos = *pos;
             // Dont scale gravity by mass
-            let accel = previous_state.force;
-            let mut new_pos = previous_state.positions[i] + velocity + accel * delta * delta;
+            let accel = state.force;
+            let mut new_pos = *pos + velocity + accel * delta * delta;
             
             // Apply boundary constraints
             let velocity = new_pos - new_prev_pos;
-            if new_pos.x < 0.0 || new_pos.x > screen_size.0 {
-                new_pos.x = new_pos.x.clamp(0.0, screen_size.0);
-                new_prev_pos.x = new_pos.x + velocity.x * previous_state.wall_damping;
+            if new_pos.x < 0.0 || new_pos.x > bounds.x {
+                new_pos.x = new_pos.x.clamp(0.0, bounds.x);
+                new_prev_pos.x = new_pos.x + velocity.x * state.wall_damping;
             }
-            if new_pos.y < 0.0 || new_pos.y > screen_size.1 {
-                new_pos.y = new_pos.y.clamp(0.0, screen_size.1);
-                new_prev_pos.y = new_pos.y + velocity.y * previous_state.wall_damping;
+            if new_pos.y < 0.0 || new_pos.y > bounds.y {
+                new_pos.y = new_pos.y.clamp(0.0, bounds.y);
+                new_prev_pos.y = new_pos.y + velocity.y * state.wall_damping;
             }
     
-            next_state.positions[i] = new_pos;
-            next_state.prev_positions[i] = new_prev_pos;
+            *pos = new_pos;
+            *prev_pos = new_prev_pos;
         };
+        if state.parallel() {
+            state.positions.par_iter_mut().zip(state.prev_positions.par_iter_mut()).enumerate().for_each(integrate);
+        } else {
+            state.positions.iter_mut().zip(state.prev_positions.iter_mut()).enumerate().for_each(integrate);
+        }
 
-        ik::solve_FABRIK(next_state, previous_state);
-        Simulation::constrain(next_state, previous_state);
-    }
+        // Move the kinematic points along their paths. Keeping the last position in prev_positions
+        // gives them a proper velocity, which the links then pass on to the attached points
+        state.time += delta;
+        for (idx, path) in state.kinematic_paths.iter() {
+            state.prev_positions[*idx] = state.positions[*idx];
+            state.positions[*idx] = path.sample(state.time);
+        }
 
+        // Pull grabbed points towards the mouse. Only positions get moved, so the velocity gets built up over time
+        for target in state.grabbed.iter() {
+            if state.fixed[target.point_idx] {
+                continue;
+            }
+            let pos = state.positions[target.point_idx];
+            state.positions[target.point_idx] += (target.target - pos) * (state.grab_strength * target.weight).clamp(0.0, 1.0);
+        }
 
-    fn constrain(next_state: &mut SimulationState, previous_state: &SimulationState) {
-        let mut link_idx: i32 = -1;
-        next_state.links.retain_mut(|link| {
-            link_idx += 1;
-            let p0 = previous_state.positions[link.from_idx];
-            let p0_mass = previous_state.masses[link.from_idx];
-            let p1 = previous_state.positions[link.to_idx];
-            let p1_mass = previous_state.masses[link.to_idx];
-            let pos_delta = p1 - p0;
-            let dist = pos_delta.length().max(f32::EPSILON);
-    
-            if dist > link.min_length && dist < link.max_length {
-                return true;
+        // The change in velocity gets added as an extra offset, which the next step picks up as velocity
+        for (idx, impulse) in state.impulses.drain(..) {
+            if state.fixed[idx] {
+                continue;
             }
-            
-            let mut diff = if dist <= link.min_length {
-                link.min_length - dist
-            } else {
-                link.max_length - dist
+            state.guard.pushed_points.push(idx);
+            state.positions[idx] += impulse / state.masses[idx].max(f32::EPSILON) * delta;
+        }
+
+        let fabrik_start = profiler::now();
+        ik::solve_FABRIK(state);
+        let constraints_start = profiler::now();
+        Simulation::constrain(state, delta);
+
+        if Simulation::USE_SLEEPING {
+            state.islands.update_sleep(&state.positions, &mut state.prev_positions, Simulation::SLEEP_VELOCITY * delta, Simulation::SLEEP_TIME, delta);
+        }
+        let end = profiler::now();
+        state.timings.integration += (fabrik_start - integration_start) as f32;
+        state.timings.fabrik += (constraints_start - fabrik_start) as f32;
+        state.timings.constraints += (end - constraints_start) as f32;
+        state.timings.substeps += 1;
+    }
+
+
+    // Solves the links batch by batch. Each batch works on the positions the previous batches produced,
+    // and since the links inside of a batch dont share points, they can be solved in parallel
+    fn constrain(state: &mut SimulationState, delta: f32) {
+        if state.link_batches_dirty {
+            state.link_batches = coloring::color_links(&state.links, state.positions.len());
+            state.link_batches_dirty = false;
+        }
+
+        let mut broken = vec![];
+        let mut offsets = vec![];
+        let parallel = state.parallel();
+        for batch in state.link_batches.iter() {
+            let positions = &state.positions;
+            let links = &state.links;
+            let islands = &state.islands;
+            let solve = |link_idx: &usize| {
+                let link = &links[*link_idx];
+                // Both points of a link are always in the same island
+                if islands.is_asleep(link.from_idx) {
+                    return (*link_idx, None);
+                }
+                (*link_idx, Simulation::link_offset(link, positions))
             };
-            diff /= dist;
-            let offset = pos_delta * diff * 0.5;
-            let offset = (offset).lerp(offset * link.stiffness, link.damping).clamp_length_max(100.0);
-            
-            if offset.length() > Simulation::MAX_LINK_STRESS {
-                next_state.removed_link_indices.push(link_idx as usize);
-                return false;
-            }
-            
-            let mass1 = p1_mass / (p0_mass + p1_mass);
-            let mass2 = p0_mass / (p0_mass + p1_mass);
-    
-            // Scale spring force by mass
-            if !previous_state.fixed[link.from_idx] {
-                next_state.positions[link.from_idx] -= offset * mass1;
+            if parallel {
+                batch.par_iter().map(solve).collect_into_vec(&mut offsets);
+            } else {
+                offsets.clear();
+                offsets.extend(batch.iter().map(solve));
             }
-            if !previous_state.fixed[link.to_idx] {
-                next_state.positions[link.to_idx] += offset * mass2;
+
+            for (link_idx, offset) in offsets.iter() {
+                let Some(offset) = offset else {
+                    continue;
+                };
+                let link = &state.links[*link_idx];
+                let p0_mass = state.masses[link.from_idx];
+                let p1_mass = state.masses[link.to_idx];
+                let load = Simulation::link_load(*offset, p0_mass, p1_mass, delta);
+                if load > link.max_stress {
+                    broken.push(*link_idx);
+                    state.link_breaks.push(LinkBreak { time: state.time, link_idx: *link_idx, from_idx: link.from_idx, to_idx: link.to_idx, load });
+                    continue;
+                }
+                let mass1 = p1_mass / (p0_mass + p1_mass);
+                let mass2 = p0_mass / (p0_mass + p1_mass);
+        
+                // Scale spring force by mass
+                if !state.fixed[link.from_idx] {
+                    state.positions[link.from_idx] -= *offset * mass1;
+                }
+                if !state.fixed[link.to_idx] {
+                    state.positions[link.to_idx] += *offset * mass2;
+                }
             }
-            true
-        });
+        }
+
+        broken.sort_unstable();
+        state.timings.breaks += broken.len();
+        state.remove_links(&broken);
+    }
+
+
+    // Returns how far the points of the link need to be moved apart, or None if the link is within its lengths
+    fn link_offset(link: &Link, positions: &[Vec2]) -> Option<Vec2> {
+        let p0 = positions[link.from_idx];
+        let p1 = positions[link.to_idx];
+        let pos_delta = p1 - p0;
+        let dist = pos_delta.length().max(f32::EPSILON);
+
+        if dist > link.min_length && dist < link.max_length {
+            return None;
+        }
+        
+        let mut diff = if dist <= link.min_length {
+            link.min_length - dist
+        } else {
+            link.max_length - dist
+        };
+        diff /= dist;
+        let offset = pos_delta * diff * 0.5;
+        Some((offset).lerp(offset * link.stiffness, link.damping).clamp_length_max(Simulation::MAX_LINK_OFFSET))
+    }
+
+
+    // Force (N) the link pulls its points together with. The offset is split between the points by their masses,
+    // and moving a point by x in one step takes a force of mass * x / delta²
+    fn link_load(offset: Vec2, from_mass: f32, to_mass: f32, delta: f32) -> f32 {
+        let reduced_mass = from_mass * to_mass / (from_mass + to_mass).max(f32::EPSILON);
+        offset.length() * reduced_mass / (delta * delta).max(f32::EPSILON)
     }
 
 
     
 
 
-    /// Draws all points and links, coloring the selection differently
-    fn draw(state: &SimulationState, selection: &Selection) {
-        for i in 0..state.links.len() {
-            let from = state.positions[state.links[i].from_idx];
-            let to = state.positions[state.links[i].to_idx];
-            if let Some(selection) = selection {
-                if selection.0 == SelectTarget::Link && selection.1 == i {
-                    draw_line(from.x, from.y, to.x, to.y, 2.0, SELECT_COLOR);
-                    continue;
-                }
+    /// Draws all points and links, coloring the selection differently, together with the debug overlays
+    fn draw(snapshot: &RenderSnapshot, selection: &Selection, debug: &DebugDraw, renderer: &mut impl Renderer) {
+        let pixel = debug.camera.pixels(1.0);
+        debug.draw_background(snapshot, renderer);
+        for i in 0..snapshot.links.len() {
+            let from = snapshot.positions[snapshot.links[i].from_idx];
+            let to = snapshot.positions[snapshot.links[i].to_idx];
+            if selection.has_link(i) {
+                renderer.line(from, to, 2.0 * pixel, SELECT_COLOR);
+                continue;
             }
-            draw_line(from.x, from.y, to.x, to.y, 2.0, DARKGRAY);
+            let color = debug.link_color(snapshot, i).unwrap_or(DARKGRAY);
+            renderer.line(from, to, 2.0 * pixel, color);
         }
 
-        for i in 0..state.positions.len() {
-            let pos = state.positions[i];
-            if let Some(selection) = selection {
-                if selection.0 == SelectTarget::Point && selection.1 == i {
-                    draw_poly_lines(pos.x, pos.y, 10, POINT_RADIUS + 2.0, 0., 4.0, SELECT_COLOR);
-                }
+        for i in 0..snapshot.positions.len() {
+            let pos = snapshot.positions[i];
+            if selection.has_point(i) {
+                renderer.regular_polygon_lines(pos, 10, (POINT_RADIUS + 2.0) * pixel, 0., 4.0 * pixel, SELECT_COLOR);
             }
-            //draw_circle(pos.x, pos.y, POINT_RADIUS, state.colors[i]);
-            draw_poly(pos.x, pos.y, 7, POINT_RADIUS, 0., state.colors[i]);
+            //renderer.circle(pos, POINT_RADIUS, state.colors[i]);
+            renderer.regular_polygon(pos, 7, POINT_RADIUS * pixel, 0., snapshot.colors[i]);
         }
+        debug.draw_foreground(snapshot, renderer);
     }
 }
 
@@ -433,5 +1576,7 @@ fn distance_from_line(point: Vec2, line_start: Vec2, line_end: Vec2) -> f32 {
 // Returns 0 if the points is on the line, 1 if its left of the line, -1 if its right
 // Thanks to https://stackoverflow.com/a/1560510
 fn side_of_line(point: Vec2, line_start: Vec2, line_end: Vec2) -> i32 {
-    (((line_end.x - line_start.x) * (point.y - line_start.y) - (line_end.y - line_start.y) * (point.x - line_start.x)) as i32).signum()
+    let cross = (line_end.x - line_start.x) * (point.y - line_start.y) - (line_end.y - line_start.y) * (point.x - line_start.x);
+    // Casting to i32 first would round everything closer than a meter to the line onto it
+    (cross > 0.0) as i32 - (cross < 0.0) as i32
 }
\ No newline at end of file
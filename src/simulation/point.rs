@@ -1,21 +1,24 @@
 use macroquad::{prelude::*, color::Color, math::Vec2};
 
+use super::KinematicPath;
+
 // Only used for letting the user define points, not in the Simulation itself
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Point {
     pub(super) position: Vec2,
     pub(super) fixed: bool,
     pub(super) mass: f32,
-    pub(super) color: Color
+    pub(super) color: Color,
+    pub(super) path: Option<KinematicPath>,
 }
-#[allow(dead_code)]
 impl Point {
     pub fn new(position: Vec2) -> Self {
         Self {
             position,
             mass: 1.0,
             fixed: false,
-            color: WHITE
+            color: WHITE,
+            path: None,
         }
     }
 
@@ -31,4 +34,9 @@ impl Point {
         self.color = val;
         self
     }
+    /// Makes the point follow the given path. The initial position gets replaced by the start of the path
+    pub fn kinematic(mut self, path: KinematicPath) -> Self {
+        self.path = Some(path);
+        self
+    }
 }
\ No newline at end of file
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+
+// Number of frames the graphs and averages go back
+const HISTORY_LENGTH: usize = 180;
+// Height of the graph in pixels, and the frame time (s) that fills it
+const GRAPH_HEIGHT: f32 = 80.0;
+const GRAPH_MAX_TIME: f32 = 1.0 / 30.0;
+const HUD_WIDTH: f32 = 360.0;
+
+
+// Seconds since some point in the past. Unlike `std::time::Instant` this also works on the web
+pub(super) fn now() -> f64 {
+    miniquad::date::now()
+}
+
+
+// Seconds it took to run f
+pub(super) fn time(f: impl FnOnce()) -> f32 {
+    let start = now();
+    f();
+    (now() - start) as f32
+}
+
+
+// Time (s) the simulation spent in each phase of its steps, summed up since it was last taken
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct StepTimings {
+    pub(super) integration: f32,
+    pub(super) fabrik: f32,
+    pub(super) constraints: f32,
+    pub(super) substeps: usize,
+    // Links that broke under their load
+    pub(super) breaks: usize,
+}
+impl StepTimings {
+    pub(super) fn add(&mut self, other: &StepTimings) {
+        self.integration += other.integration;
+        self.fabrik += other.fabrik;
+        self.constraints += other.constraints;
+        self.substeps += other.substeps;
+        self.breaks += other.breaks;
+    }
+}
+
+
+/// Where the time of one frame went. Times are in seconds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameProfile {
+    /// Time since the previous frame started
+    pub frame_time: f32,
+    pub integration: f32,
+    pub fabrik: f32,
+    pub constraints: f32,
+    /// Handling the input and the windows
+    pub interaction: f32,
+    pub draw: f32,
+    /// Steps that finished during this frame
+    pub substeps: usize,
+    pub breaks: usize,
+    pub points: usize,
+    pub links: usize,
+    pub ik_chains: usize,
+}
+impl FrameProfile {
+    // (name, time, color) of the phases, in the order they get stacked in the graph
+    fn phases(&self) -> [(&'static str, f32, Color); 5] {
+        [
+            ("Integration", self.integration, SKYBLUE),
+            ("FABRIK", self.fabrik, MAGENTA),
+            ("Constraints", self.constraints, ORANGE),
+            ("Interaction", self.interaction, GREEN),
+            ("Draw", self.draw, YELLOW),
+        ]
+    }
+}
+
+
+/// Keeps the profiles of the last frames, for the HUD and for automated performance tracking
+#[derive(Debug, Default)]
+pub struct Profiler {
+    history: VecDeque<FrameProfile>,
+    // Start of the current frame
+    frame_start: Option<f64>,
+}
+impl Profiler {
+    // Starts a new frame and returns the time since the last one started
+    pub(super) fn begin_frame(&mut self) -> f32 {
+        let time = now();
+        let frame_time = self.frame_start.map_or(0.0, |start| (time - start) as f32);
+        self.frame_start = Some(time);
+        frame_time
+    }
+
+
+    pub(super) fn push(&mut self, profile: FrameProfile) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(profile);
+    }
+
+
+    /// Profile of the last finished frame
+    pub fn last_frame(&self) -> Option<&FrameProfile> {
+        self.history.back()
+    }
+
+
+    /// Profiles of the last frames, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.history.iter()
+    }
+
+
+    /// Average times and counts over the history
+    pub fn average(&self) -> FrameProfile {
+        let count = self.history.len().max(1);
+        let mut sum = FrameProfile::default();
+        for profile in self.history.iter() {
+            sum.frame_time += profile.frame_time;
+            sum.integration += profile.integration;
+            sum.fabrik += profile.fabrik;
+            sum.constraints += profile.constraints;
+            sum.interaction += profile.interaction;
+            sum.draw += profile.draw;
+            sum.substeps += profile.substeps;
+            sum.breaks += profile.breaks;
+        }
+        let last = self.last_frame().copied().unwrap_or_default();
+        FrameProfile {
+            frame_time: sum.frame_time / count as f32,
+            integration: sum.integration / count as f32,
+            fabrik: sum.fabrik / count as f32,
+            constraints: sum.constraints / count as f32,
+            interaction: sum.interaction / count as f32,
+            draw: sum.draw / count as f32,
+            substeps: (sum.substeps as f32 / count as f32).round() as usize,
+            breaks: (sum.breaks as f32 / count as f32).round() as usize,
+            points: last.points,
+            links: last.links,
+            ik_chains: last.ik_chains,
+        }
+    }
+
+
+    pub fn breaks_per_second(&self) -> f32 {
+        let time = self.history.iter().map(|profile| profile.frame_time).sum::<f32>();
+        let breaks = self.history.iter().map(|profile| profile.breaks).sum::<usize>();
+        if time > 0.0 { breaks as f32 / time } else { 0.0 }
+    }
+
+
+    // Frame time graph with the phases stacked on top of each other, and the numbers next to it.
+    // Drawn in screen pixels at the top center
+    pub(super) fn draw_hud(&self) {
+        let left = (screen_width() - HUD_WIDTH) * 0.5;
+        let top = 10.0;
+        draw_rectangle(left, top, HUD_WIDTH, GRAPH_HEIGHT + 160.0, Color::new(0.0, 0.0, 0.0, 0.6));
+
+        let graph_bottom = top + GRAPH_HEIGHT;
+        let column_width = HUD_WIDTH / HISTORY_LENGTH as f32;
+        let scale = GRAPH_HEIGHT / GRAPH_MAX_TIME;
+        for (i, profile) in self.history.iter().enumerate() {
+            let x = left + i as f32 * column_width;
+            let frame_height = (profile.frame_time * scale).min(GRAPH_HEIGHT);
+            draw_rectangle(x, graph_bottom - frame_height, column_width, frame_height, DARKGRAY);
+            let mut y = graph_bottom;
+            for (_, time, color) in profile.phases() {
+                let height = (time * scale).min(y - top);
+                draw_rectangle(x, y - height, column_width, height, color);
+                y -= height;
+            }
+        }
+        // 60 fps line
+        let target_y = graph_bottom - scale / 60.0;
+        draw_line(left, target_y, left + HUD_WIDTH, target_y, 1.0, WHITE);
+
+        let average = self.average();
+        let fps = if average.frame_time > 0.0 { 1.0 / average.frame_time } else { 0.0 };
+        let mut y = graph_bottom + 18.0;
+        let mut line = |text: &str, color: Color| {
+            draw_text(text, left + 6.0, y, 16.0, color);
+            y += 16.0;
+        };
+        line(&format!("Frame: {:.2} ms ({:.0} fps)", average.frame_time * 1000.0, fps), WHITE);
+        for (name, time, color) in average.phases() {
+            line(&format!("{}: {:.3} ms", name, time * 1000.0), color);
+        }
+        line(&format!("Points: {}, links: {}, IK chains: {}", average.points, average.links, average.ik_chains), WHITE);
+        line(&format!("Substeps: {} per frame, breaks: {:.1}/s", average.substeps, self.breaks_per_second()), WHITE);
+    }
+}
@@ -0,0 +1,482 @@
+use macroquad::math::Vec2;
+
+use super::snapshot::RenderSnapshot;
+
+
+// Side length (in m) of a grid cell
+const CELL_SIZE: f32 = 0.5;
+// When points fly far apart the cells get larger instead of the grid getting huge
+const MAX_CELLS: usize = 256 * 256;
+
+
+/// What a ray hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RayTarget {
+    Link(usize),
+    // The walls at the edges of the bounds
+    Bounds,
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub target: RayTarget,
+    pub position: Vec2,
+    // Unit normal of the surface that got hit, facing towards the origin of the ray
+    pub normal: Vec2,
+    // Distance from the origin of the ray
+    pub distance: f32,
+}
+
+
+// Uniform grid over the points and links of a snapshot, rebuilt once per frame.
+// Every cell knows the points inside of it and the links whose bounding box overlaps it.
+// The indices of all cells are packed into one list, cell i owns items[cell_start[i]..cell_start[i + 1]]
+#[derive(Debug, Default)]
+pub(super) struct SpatialGrid {
+    origin: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    point_cell_start: Vec<usize>,
+    points: Vec<usize>,
+    link_cell_start: Vec<usize>,
+    links: Vec<usize>,
+    // (cell, item) pairs, kept around so the allocation can be reused
+    pairs: Vec<(usize, usize)>,
+}
+impl SpatialGrid {
+    pub(super) fn rebuild(&mut self, snapshot: &RenderSnapshot) {
+        let positions = &snapshot.positions;
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        for pos in positions.iter().filter(|pos| pos.is_finite()) {
+            min = min.min(*pos);
+            max = max.max(*pos);
+        }
+        if min.x > max.x {
+            min = Vec2::ZERO;
+            max = Vec2::ZERO;
+        }
+
+        let extent = max - min;
+        self.cell_size = CELL_SIZE;
+        while ((extent.x / self.cell_size) as usize + 1) * ((extent.y / self.cell_size) as usize + 1) > MAX_CELLS {
+            self.cell_size *= 2.0;
+        }
+        self.origin = min;
+        self.width = (extent.x / self.cell_size) as usize + 1;
+        self.height = (extent.y / self.cell_size) as usize + 1;
+        let num_cells = self.width * self.height;
+
+        let mut pairs = std::mem::take(&mut self.pairs);
+        pairs.clear();
+        for (i, pos) in positions.iter().enumerate() {
+            if pos.is_finite() {
+                let (x, y) = self.clamped_cell(*pos);
+                pairs.push((y * self.width + x, i));
+            }
+        }
+        pack(&pairs, num_cells, &mut self.point_cell_start, &mut self.points);
+
+        pairs.clear();
+        for (i, link) in snapshot.links.iter().enumerate() {
+            let from = positions[link.from_idx];
+            let to = positions[link.to_idx];
+            if !from.is_finite() || !to.is_finite() {
+                continue;
+            }
+            let (x0, y0) = self.clamped_cell(from.min(to));
+            let (x1, y1) = self.clamped_cell(from.max(to));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    pairs.push((y * self.width + x, i));
+                }
+            }
+        }
+        pack(&pairs, num_cells, &mut self.link_cell_start, &mut self.links);
+        self.pairs = pairs;
+    }
+
+
+    // Closest point that is at most max_distance away and passes the filter
+    pub(super) fn nearest_point(&self, positions: &[Vec2], position: Vec2, max_distance: f32, filter: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut nearest: Option<(usize, f32)> = None;
+        self.search_rings(position, max_distance, &mut nearest, |cell, nearest| {
+            for point_idx in self.cell_points(cell) {
+                let dist = positions[*point_idx].distance(position);
+                if dist <= max_distance && nearest.is_none_or(|(_, nearest_dist)| dist < nearest_dist) && filter(*point_idx) {
+                    *nearest = Some((*point_idx, dist));
+                }
+            }
+        });
+        nearest.map(|(point_idx, _)| point_idx)
+    }
+
+
+    // Closest link that is at most max_distance away
+    pub(super) fn nearest_link(&self, snapshot: &RenderSnapshot, position: Vec2, max_distance: f32) -> Option<usize> {
+        let mut nearest: Option<(usize, f32)> = None;
+        self.search_rings(position, max_distance, &mut nearest, |cell, nearest| {
+            for link_idx in self.cell_links(cell) {
+                let link = &snapshot.links[*link_idx];
+                let dist = super::distance_from_line(position, snapshot.positions[link.from_idx], snapshot.positions[link.to_idx]);
+                if dist <= max_distance && nearest.is_none_or(|(_, nearest_dist)| dist < nearest_dist) {
+                    *nearest = Some((*link_idx, dist));
+                }
+            }
+        });
+        nearest.map(|(link_idx, _)| link_idx)
+    }
+
+
+    pub(super) fn points_in_rect(&self, positions: &[Vec2], min: Vec2, max: Vec2) -> Vec<usize> {
+        let mut points = vec![];
+        if let Some((x0, y0, x1, y1)) = self.cell_range(min, max) {
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    points.extend(self.cell_points(y * self.width + x).iter().filter(|idx| {
+                        let pos = positions[**idx];
+                        pos.cmpge(min).all() && pos.cmple(max).all()
+                    }));
+                }
+            }
+        }
+        points.sort_unstable();
+        points
+    }
+
+
+    pub(super) fn points_in_radius(&self, positions: &[Vec2], center: Vec2, radius: f32) -> Vec<usize> {
+        let mut points = self.points_in_rect(positions, center - Vec2::splat(radius), center + Vec2::splat(radius));
+        points.retain(|idx| positions[*idx].distance_squared(center) <= radius * radius);
+        points
+    }
+
+
+    // Links that have at least a part inside of the rectangle
+    pub(super) fn links_in_rect(&self, snapshot: &RenderSnapshot, min: Vec2, max: Vec2) -> Vec<usize> {
+        let mut links = vec![];
+        if let Some((x0, y0, x1, y1)) = self.cell_range(min, max) {
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    links.extend_from_slice(self.cell_links(y * self.width + x));
+                }
+            }
+        }
+        // Links are stored in every cell they overlap
+        links.sort_unstable();
+        links.dedup();
+        links.retain(|idx| {
+            let link = &snapshot.links[*idx];
+            segment_overlaps_rect(snapshot.positions[link.from_idx], snapshot.positions[link.to_idx], min, max)
+        });
+        links
+    }
+
+
+    // Walks the cells along the ray (Amanatides & Woo) and stops at the first cell that
+    // is further away than the closest hit
+    pub(super) fn raycast_links(&self, snapshot: &RenderSnapshot, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec2::ZERO || self.links.is_empty() {
+            return None;
+        }
+
+        // Clip the ray to the grid
+        let grid_max = self.origin + Vec2::new(self.width as f32, self.height as f32) * self.cell_size;
+        let mut t_enter = 0.0_f32;
+        let mut t_exit = max_distance;
+        for axis in 0..2 {
+            if direction[axis].abs() < f32::EPSILON {
+                if origin[axis] < self.origin[axis] || origin[axis] > grid_max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (self.origin[axis] - origin[axis]) / direction[axis];
+            let t1 = (grid_max[axis] - origin[axis]) / direction[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let (mut x, mut y) = self.clamped_cell(origin + direction * t_enter);
+        let step = [direction.x.signum() as isize, direction.y.signum() as isize];
+        let mut t_max = [f32::INFINITY; 2];
+        let mut t_delta = [f32::INFINITY; 2];
+        for axis in 0..2 {
+            if direction[axis].abs() < f32::EPSILON {
+                continue;
+            }
+            let cell = if axis == 0 { x } else { y } as f32 + if step[axis] > 0 { 1.0 } else { 0.0 };
+            t_max[axis] = (self.origin[axis] + cell * self.cell_size - origin[axis]) / direction[axis];
+            t_delta[axis] = self.cell_size / direction[axis].abs();
+        }
+
+        let mut nearest: Option<RayHit> = None;
+        loop {
+            for link_idx in self.cell_links(y * self.width + x) {
+                let link = &snapshot.links[*link_idx];
+                let hit = ray_segment(origin, direction, snapshot.positions[link.from_idx], snapshot.positions[link.to_idx]);
+                if let Some((distance, normal)) = hit {
+                    if distance <= max_distance && nearest.is_none_or(|nearest| distance < nearest.distance) {
+                        nearest = Some(RayHit {
+                            target: RayTarget::Link(*link_idx),
+                            position: origin + direction * distance,
+                            normal,
+                            distance,
+                        });
+                    }
+                }
+            }
+
+            let axis = if t_max[0] < t_max[1] { 0 } else { 1 };
+            if t_max[axis] > t_exit || nearest.is_some_and(|nearest| nearest.distance <= t_max[axis]) {
+                break;
+            }
+            let cell = if axis == 0 { &mut x } else { &mut y };
+            let size = if axis == 0 { self.width } else { self.height };
+            match cell.checked_add_signed(step[axis]) {
+                Some(next) if next < size => *cell = next,
+                _ => break,
+            }
+            t_max[axis] += t_delta[axis];
+        }
+        nearest
+    }
+
+
+    // Goes through the cells in growing square rings around the position, until the rings
+    // are further away than max_distance or than what was found so far
+    fn search_rings(&self, position: Vec2, max_distance: f32, nearest: &mut Option<(usize, f32)>, mut search_cell: impl FnMut(usize, &mut Option<(usize, f32)>)) {
+        if self.width == 0 || !position.is_finite() {
+            return;
+        }
+        let cell = ((position - self.origin) / self.cell_size).floor();
+        let (cx, cy) = (cell.x as i64, cell.y as i64);
+        let (width, height) = (self.width as i64, self.height as i64);
+        // Rings closer than this dont overlap the grid, rings further away are outside of it
+        let first_ring = (-cx).max(cx - width + 1).max(-cy).max(cy - height + 1).max(0);
+        let last_ring = cx.max(width - 1 - cx).max(cy).max(height - 1 - cy);
+
+        for ring in first_ring..=last_ring {
+            // Every cell of the ring is at least this far away from the position
+            let ring_distance = (ring - 1).max(0) as f32 * self.cell_size;
+            if ring_distance > max_distance || nearest.is_some_and(|(_, dist)| dist < ring_distance) {
+                break;
+            }
+            for y in (cy - ring).max(0)..=(cy + ring).min(height - 1) {
+                let is_edge_row = y == cy - ring || y == cy + ring;
+                let mut search_x = |x: i64| {
+                    if (0..width).contains(&x) {
+                        search_cell((y * width + x) as usize, nearest);
+                    }
+                };
+                if is_edge_row {
+                    for x in (cx - ring)..=(cx + ring) {
+                        search_x(x);
+                    }
+                } else {
+                    search_x(cx - ring);
+                    if ring > 0 {
+                        search_x(cx + ring);
+                    }
+                }
+            }
+        }
+    }
+
+
+    fn clamped_cell(&self, pos: Vec2) -> (usize, usize) {
+        let cell = ((pos - self.origin) / self.cell_size).floor();
+        (
+            (cell.x.max(0.0) as usize).min(self.width.saturating_sub(1)),
+            (cell.y.max(0.0) as usize).min(self.height.saturating_sub(1)),
+        )
+    }
+
+
+    // Cells overlapping the rectangle, None if it is outside of the grid
+    fn cell_range(&self, min: Vec2, max: Vec2) -> Option<(usize, usize, usize, usize)> {
+        let grid_max = self.origin + Vec2::new(self.width as f32, self.height as f32) * self.cell_size;
+        if self.width == 0 || min.x > max.x || min.y > max.y || max.cmplt(self.origin).any() || min.cmpgt(grid_max).any() {
+            return None;
+        }
+        let (x0, y0) = self.clamped_cell(min);
+        let (x1, y1) = self.clamped_cell(max);
+        Some((x0, y0, x1, y1))
+    }
+
+
+    // Top left corner, side length and number of points and links of every cell that isnt empty
+    pub(super) fn occupied_cells(&self) -> impl Iterator<Item = (Vec2, f32, usize, usize)> + '_ {
+        (0..self.width * self.height).filter_map(|cell| {
+            let (points, links) = (self.cell_points(cell).len(), self.cell_links(cell).len());
+            let corner = self.origin + Vec2::new((cell % self.width) as f32, (cell / self.width) as f32) * self.cell_size;
+            (points + links > 0).then_some((corner, self.cell_size, points, links))
+        })
+    }
+
+
+    fn cell_points(&self, cell: usize) -> &[usize] {
+        &self.points[self.point_cell_start[cell]..self.point_cell_start[cell + 1]]
+    }
+
+
+    fn cell_links(&self, cell: usize) -> &[usize] {
+        &self.links[self.link_cell_start[cell]..self.link_cell_start[cell + 1]]
+    }
+}
+
+
+// Sorts the items into their cells with a counting sort
+fn pack(pairs: &[(usize, usize)], num_cells: usize, cell_start: &mut Vec<usize>, items: &mut Vec<usize>) {
+    cell_start.clear();
+    cell_start.resize(num_cells + 1, 0);
+    for (cell, _) in pairs.iter() {
+        cell_start[cell + 1] += 1;
+    }
+    for cell in 0..num_cells {
+        cell_start[cell + 1] += cell_start[cell];
+    }
+    items.clear();
+    items.resize(pairs.len(), 0);
+    // Use the start of each cell as its write cursor, afterwards every start has moved to the start of the next cell
+    for (cell, item) in pairs.iter() {
+        items[cell_start[*cell]] = *item;
+        cell_start[*cell] += 1;
+    }
+    for cell in (1..=num_cells).rev() {
+        cell_start[cell] = cell_start[cell - 1];
+    }
+    cell_start[0] = 0;
+}
+
+
+// Distance along the ray and the normal of the segment, if the ray hits it
+fn ray_segment(origin: Vec2, direction: Vec2, start: Vec2, end: Vec2) -> Option<(f32, Vec2)> {
+    let segment = end - start;
+    let denominator = direction.perp_dot(segment);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+    let to_start = start - origin;
+    let distance = to_start.perp_dot(segment) / denominator;
+    let h = to_start.perp_dot(direction) / denominator;
+    if distance < 0.0 || !(0.0..=1.0).contains(&h) {
+        return None;
+    }
+    let normal = segment.perp().normalize_or_zero();
+    Some((distance, if normal.dot(direction) > 0.0 { -normal } else { normal }))
+}
+
+
+// Hit with the walls of the area from (0, 0) to bounds, from the inside or the outside
+pub(super) fn raycast_bounds(origin: Vec2, direction: Vec2, max_distance: f32, bounds: Vec2) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    let mut nearest: Option<RayHit> = None;
+    for axis in 0..2 {
+        if direction[axis].abs() < f32::EPSILON {
+            continue;
+        }
+        let other = 1 - axis;
+        for wall in [0.0, bounds[axis]] {
+            let distance = (wall - origin[axis]) / direction[axis];
+            let position = origin + direction * distance;
+            if distance < 0.0 || distance > max_distance || position[other] < 0.0 || position[other] > bounds[other] {
+                continue;
+            }
+            if nearest.is_none_or(|nearest| distance < nearest.distance) {
+                let mut normal = Vec2::ZERO;
+                normal[axis] = -direction[axis].signum();
+                nearest = Some(RayHit {
+                    target: RayTarget::Bounds,
+                    position,
+                    normal,
+                    distance,
+                });
+            }
+        }
+    }
+    nearest
+}
+
+
+// Liang-Barsky clipping of the segment against the rectangle
+fn segment_overlaps_rect(start: Vec2, end: Vec2, min: Vec2, max: Vec2) -> bool {
+    let delta = end - start;
+    let mut t0 = 0.0_f32;
+    let mut t1 = 1.0_f32;
+    for axis in 0..2 {
+        if delta[axis].abs() < f32::EPSILON {
+            if start[axis] < min[axis] || start[axis] > max[axis] {
+                return false;
+            }
+            continue;
+        }
+        let a = (min[axis] - start[axis]) / delta[axis];
+        let b = (max[axis] - start[axis]) / delta[axis];
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    t0 <= t1
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::RayTarget;
+    use crate::simulation::{Link, Point, Simulation};
+
+
+    const DELTA: f32 = 1.0 / 240.0;
+
+
+    // Bounds of 10 x 5 m with two fixed vertical links, at x = 3 and x = 6
+    fn posts() -> Simulation {
+        let mut simulation = Simulation::new();
+        simulation.set_bounds(Vec2::new(10.0, 5.0));
+        simulation.add_points(&[
+            Point::new(Vec2::new(3.0, 1.0)).fixed(),
+            Point::new(Vec2::new(3.0, 3.0)).fixed(),
+            Point::new(Vec2::new(6.0, 1.0)).fixed(),
+            Point::new(Vec2::new(6.0, 3.0)).fixed(),
+        ]);
+        simulation.add_link(Link::new(0, 1));
+        simulation.add_link(Link::new(2, 3));
+        simulation.step_headless(DELTA);
+        simulation
+    }
+
+
+    #[test]
+    fn ray_hits_the_nearest_link() {
+        let simulation = posts();
+        let hit = simulation.raycast(Vec2::new(1.0, 2.0), Vec2::X, 100.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Link(0));
+        assert!(hit.position.distance(Vec2::new(3.0, 2.0)) < 1e-4);
+        assert!((hit.distance - 2.0).abs() < 1e-4);
+        assert_eq!(hit.normal, Vec2::NEG_X);
+
+        let hit = simulation.raycast(Vec2::new(9.0, 2.0), Vec2::NEG_X, 100.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Link(1));
+        assert_eq!(hit.normal, Vec2::X);
+    }
+
+
+    #[test]
+    fn ray_past_the_links_hits_the_bounds() {
+        let simulation = posts();
+        let hit = simulation.raycast(Vec2::new(1.0, 4.0), Vec2::X, 100.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Bounds);
+        assert!(hit.position.distance(Vec2::new(10.0, 4.0)) < 1e-4);
+        assert_eq!(hit.normal, Vec2::NEG_X);
+        // Too short to reach anything
+        assert!(simulation.raycast(Vec2::new(1.0, 2.0), Vec2::X, 1.0).is_none());
+    }
+}
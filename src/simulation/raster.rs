@@ -0,0 +1,246 @@
+use macroquad::{color::Color, math::Vec2, texture::Image};
+
+use super::{render::{RenderTexture, Renderer, Space}, Camera};
+
+
+// 3x5 pixel font, one row of 3 bits per octal digit from the top. Lowercase letters use the uppercase ones
+const FONT: [(char, u16); 57] = [
+    ('0', 0o75557), ('1', 0o26227), ('2', 0o71747), ('3', 0o71317), ('4', 0o55711),
+    ('5', 0o74717), ('6', 0o74757), ('7', 0o71122), ('8', 0o75757), ('9', 0o75717),
+    ('A', 0o25755), ('B', 0o65656), ('C', 0o34443), ('D', 0o65556), ('E', 0o74647),
+    ('F', 0o74644), ('G', 0o34553), ('H', 0o55755), ('I', 0o72227), ('J', 0o11152),
+    ('K', 0o55655), ('L', 0o44447), ('M', 0o57755), ('N', 0o65555), ('O', 0o25552),
+    ('P', 0o65644), ('Q', 0o25563), ('R', 0o65655), ('S', 0o34216), ('T', 0o72222),
+    ('U', 0o55557), ('V', 0o55552), ('W', 0o55775), ('X', 0o55255), ('Y', 0o55222),
+    ('Z', 0o71247), ('.', 0o00002), (',', 0o00024), (':', 0o02020), ('%', 0o51245),
+    ('/', 0o11244), ('-', 0o00700), ('+', 0o02720), ('(', 0o12221), (')', 0o42224),
+    ('=', 0o07070), ('_', 0o00007), ('<', 0o12421), ('>', 0o42124), ('²', 0o61270),
+    ('*', 0o05250), ('?', 0o71302), ('\'', 0o22000), ('!', 0o22202), ('[', 0o32223),
+    (']', 0o62226), (' ', 0o00000),
+];
+// Drawn for characters that arent in the font, a question mark
+const UNKNOWN_GLYPH: u16 = 0o71302;
+// Font pixels per font size, and glyph pixels (with the gap) per character
+const FONT_SCALE: f32 = 1.0 / 7.0;
+const GLYPH_ADVANCE: f32 = 4.0;
+
+
+/// Draws into an RGBA image on the CPU, so frames can be exported without a GPU or a window.
+/// Without anti aliasing, every pixel is either covered or not
+#[derive(Debug)]
+pub struct SoftwareRenderer {
+    camera: Camera,
+    space: Space,
+    image: Image,
+}
+impl SoftwareRenderer {
+    /// Image of the size of the screen of the camera, filled with the background
+    pub fn new(camera: &Camera, background: Color) -> Self {
+        let size = camera.screen_size.max(Vec2::ONE);
+        Self {
+            camera: camera.clone(),
+            space: Space::World,
+            image: Image::gen_image_color(size.x as u16, size.y as u16, background),
+        }
+    }
+
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+
+    fn pos(&self, pos: Vec2) -> Vec2 {
+        match self.space {
+            Space::World => self.camera.world_to_screen(pos),
+            Space::Screen => pos,
+        }
+    }
+
+
+    fn length(&self, length: f32) -> f32 {
+        match self.space {
+            Space::World => length * self.camera.scale(),
+            Space::Screen => length,
+        }
+    }
+
+
+    // Blends the color over the pixel
+    fn blend(&mut self, x: usize, y: usize, color: Color) {
+        let idx = (y * self.image.width as usize + x) * 4;
+        let pixel = &mut self.image.bytes[idx..idx + 4];
+        let alpha = color.a.clamp(0.0, 1.0);
+        for (channel, value) in pixel.iter_mut().zip([color.r, color.g, color.b]) {
+            let old = *channel as f32 / 255.0;
+            *channel = ((old + (value - old) * alpha) * 255.0).round() as u8;
+        }
+        pixel[3] = ((pixel[3] as f32 / 255.0 + (1.0 - pixel[3] as f32 / 255.0) * alpha) * 255.0).round() as u8;
+    }
+
+
+    // Rows and columns of the pixels whose centers might be inside of min to max, clipped to the image
+    fn pixel_range(&self, min: Vec2, max: Vec2) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+        let size = Vec2::new(self.image.width as f32, self.image.height as f32);
+        let min = (min - 0.5).ceil().max(Vec2::ZERO);
+        let max = (max - 0.5).floor().min(size - 1.0);
+        if !(min.cmple(max).all() && min.is_finite() && max.is_finite()) {
+            return None;
+        }
+        Some((min.y as usize..max.y as usize + 1, min.x as usize..max.x as usize + 1))
+    }
+
+
+    // Fills the polygon (in pixels) with the even-odd rule, one row at a time
+    fn fill_pixels(&mut self, points: &[Vec2], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+        let min = points.iter().fold(Vec2::splat(f32::MAX), |min, pos| min.min(*pos));
+        let max = points.iter().fold(Vec2::splat(f32::MIN), |max, pos| max.max(*pos));
+        let Some((rows, columns)) = self.pixel_range(min, max) else {
+            return;
+        };
+        let mut crossings = vec![];
+        for y in rows {
+            let center_y = y as f32 + 0.5;
+            crossings.clear();
+            for i in 0..points.len() {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                if (a.y <= center_y) != (b.y <= center_y) {
+                    crossings.push(a.x + (center_y - a.y) / (b.y - a.y) * (b.x - a.x));
+                }
+            }
+            crossings.sort_unstable_by(f32::total_cmp);
+            for span in crossings.chunks_exact(2) {
+                let start = ((span[0] - 0.5).ceil().max(columns.start as f32)) as usize;
+                let end = ((span[1] - 0.5).floor().min(columns.end as f32 - 1.0) + 1.0).max(0.0) as usize;
+                for x in start..end {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+
+    // Every pixel whose center is between the two radii (in pixels) around the center
+    fn fill_ring(&mut self, center: Vec2, inner: f32, outer: f32, color: Color) {
+        let Some((rows, columns)) = self.pixel_range(center - outer, center + outer) else {
+            return;
+        };
+        for y in rows {
+            for x in columns.clone() {
+                let distance = center.distance(Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+                if distance <= outer && distance >= inner {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+}
+impl Renderer for SoftwareRenderer {
+    fn set_space(&mut self, space: Space) {
+        self.space = space;
+    }
+
+
+    // A rectangle around the line, at least one pixel wide so thin lines dont disappear
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: f32, color: Color) {
+        let (from, to) = (self.pos(from), self.pos(to));
+        let half_width = self.length(thickness).max(1.0) * 0.5;
+        let dir = (to - from).normalize_or_zero();
+        let dir = if dir == Vec2::ZERO { Vec2::X } else { dir };
+        let side = dir.perp() * half_width;
+        // Square caps, so the lines of a polygon meet at the corners
+        let (from, to) = (from - dir * half_width, to + dir * half_width);
+        self.fill_pixels(&[from + side, to + side, to - side, from - side], color);
+    }
+
+
+    fn circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let center = self.pos(center);
+        let radius = self.length(radius).max(0.5);
+        self.fill_ring(center, 0.0, radius, color);
+    }
+
+
+    fn circle_lines(&mut self, center: Vec2, radius: f32, thickness: f32, color: Color) {
+        let center = self.pos(center);
+        let radius = self.length(radius);
+        let half_width = self.length(thickness).max(1.0) * 0.5;
+        self.fill_ring(center, radius - half_width, radius + half_width, color);
+    }
+
+
+    fn polygon(&mut self, points: &[Vec2], color: Color) {
+        let points = points.iter().map(|pos| self.pos(*pos)).collect::<Vec<_>>();
+        self.fill_pixels(&points, color);
+    }
+
+
+    fn polygon_lines(&mut self, points: &[Vec2], thickness: f32, color: Color) {
+        for i in 0..points.len() {
+            self.line(points[i], points[(i + 1) % points.len()], thickness, color);
+        }
+    }
+
+
+    fn text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) {
+        let pos = self.pos(pos);
+        let scale = (self.length(size) * FONT_SCALE).round().max(1.0);
+        for (i, c) in text.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let glyph = FONT.iter().find(|(glyph_char, _)| *glyph_char == c).map_or(UNKNOWN_GLYPH, |(_, glyph)| *glyph);
+            // The baseline is below the glyph
+            let corner = pos + Vec2::new(i as f32 * GLYPH_ADVANCE, -5.0) * scale;
+            for row in 0..5 {
+                for column in 0..3 {
+                    if glyph >> ((4 - row) * 3 + 2 - column) & 1 == 1 {
+                        let min = corner + Vec2::new(column as f32, row as f32) * scale;
+                        self.fill_pixels(&[min, min + Vec2::new(scale, 0.0), min + scale, min + Vec2::new(0.0, scale)], color);
+                    }
+                }
+            }
+        }
+    }
+
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        let scale = (self.length(size) * FONT_SCALE).round().max(1.0);
+        text.chars().count() as f32 * GLYPH_ADVANCE * scale
+    }
+
+
+    // Nearest texel for every pixel center inside of the triangle, multiplied with the color
+    fn textured_triangle(&mut self, points: [Vec2; 3], uvs: [Vec2; 3], texture: &RenderTexture, color: Color) {
+        let [a, b, c] = points.map(|pos| self.pos(pos));
+        let area = (b - a).perp_dot(c - a);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        let Some((rows, columns)) = self.pixel_range(a.min(b).min(c), a.max(b).max(c)) else {
+            return;
+        };
+        let image = texture.image();
+        let size = Vec2::new(image.width as f32, image.height as f32);
+        for y in rows {
+            for x in columns.clone() {
+                let pos = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [(c - b).perp_dot(pos - b), (a - c).perp_dot(pos - c), (b - a).perp_dot(pos - a)].map(|w| w / area);
+                if weights.iter().any(|w| *w < 0.0) {
+                    continue;
+                }
+                let uv = uvs[0] * weights[0] + uvs[1] * weights[1] + uvs[2] * weights[2];
+                let texel = (uv * size).floor().clamp(Vec2::ZERO, size - 1.0);
+                let texel = image.get_pixel(texel.x as u32, texel.y as u32);
+                let tinted = Color::new(texel.r * color.r, texel.g * color.g, texel.b * color.b, texel.a * color.a);
+                self.blend(x, y, tinted);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::cell::OnceCell;
+
+use macroquad::{models::Vertex, prelude::*};
+
+use super::Camera;
+
+
+/// What the positions and sizes given to a renderer are in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Space {
+    /// Meters, seen through the camera
+    #[default]
+    World,
+    /// Screen pixels, for text and legends
+    Screen,
+}
+
+
+/// An image for textured triangles. It stays on the CPU so every renderer can read it,
+/// the macroquad renderer uploads it the first time it gets drawn
+#[derive(Debug)]
+pub struct RenderTexture {
+    image: Image,
+    texture: OnceCell<Texture2D>,
+}
+impl RenderTexture {
+    pub fn new(image: Image) -> Self {
+        Self {
+            image,
+            texture: OnceCell::new(),
+        }
+    }
+
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+
+    fn texture(&self) -> &Texture2D {
+        self.texture.get_or_init(|| Texture2D::from_image(&self.image))
+    }
+}
+
+
+/// Where Simulation::draw draws to. Starts out in world space, see Space
+pub trait Renderer {
+    fn set_space(&mut self, space: Space);
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: f32, color: Color);
+    fn circle(&mut self, center: Vec2, radius: f32, color: Color);
+    fn circle_lines(&mut self, center: Vec2, radius: f32, thickness: f32, color: Color);
+    /// Filled convex polygon
+    fn polygon(&mut self, points: &[Vec2], color: Color);
+    /// Outline of the closed polygon
+    fn polygon_lines(&mut self, points: &[Vec2], thickness: f32, color: Color);
+    /// Text with its baseline starting at pos, size is the font size
+    fn text(&mut self, text: &str, pos: Vec2, size: f32, color: Color);
+    fn text_width(&self, text: &str, size: f32) -> f32;
+    /// Triangle with the part of the texture between the uvs (0 to 1) stretched onto it, tinted with the color
+    fn textured_triangle(&mut self, points: [Vec2; 3], uvs: [Vec2; 3], texture: &RenderTexture, color: Color);
+
+    /// Polygon with `sides` corners on the circle, rotated by `rotation` degrees
+    fn regular_polygon(&mut self, center: Vec2, sides: u8, radius: f32, rotation: f32, color: Color) {
+        self.polygon(&regular_polygon(center, sides, radius, rotation), color);
+    }
+
+    fn regular_polygon_lines(&mut self, center: Vec2, sides: u8, radius: f32, rotation: f32, thickness: f32, color: Color) {
+        self.polygon_lines(&regular_polygon(center, sides, radius, rotation), thickness, color);
+    }
+
+    fn rect(&mut self, corner: Vec2, size: Vec2, color: Color) {
+        self.polygon(&rect_corners(corner, size), color);
+    }
+
+    fn rect_lines(&mut self, corner: Vec2, size: Vec2, thickness: f32, color: Color) {
+        self.polygon_lines(&rect_corners(corner, size), thickness, color);
+    }
+}
+
+
+// Corners of the polygon, the same ones macroquad uses
+fn regular_polygon(center: Vec2, sides: u8, radius: f32, rotation: f32) -> Vec<Vec2> {
+    let rotation = rotation.to_radians();
+    (0..sides).map(|i| {
+        let angle = i as f32 / sides as f32 * std::f32::consts::TAU + rotation;
+        center + Vec2::from_angle(angle) * radius
+    }).collect()
+}
+
+
+fn rect_corners(corner: Vec2, size: Vec2) -> [Vec2; 4] {
+    [corner, corner + Vec2::new(size.x, 0.0), corner + size, corner + Vec2::new(0.0, size.y)]
+}
+
+
+/// Draws to the screen, the world through the camera
+pub struct MacroquadRenderer<'a> {
+    camera: &'a Camera,
+}
+impl<'a> MacroquadRenderer<'a> {
+    /// Expects the camera to be set already, like it is while Simulation::update draws
+    pub fn new(camera: &'a Camera) -> Self {
+        Self { camera }
+    }
+}
+impl Renderer for MacroquadRenderer<'_> {
+    fn set_space(&mut self, space: Space) {
+        match space {
+            Space::World => set_camera(&self.camera.to_macroquad()),
+            Space::Screen => set_default_camera(),
+        }
+    }
+
+
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: f32, color: Color) {
+        draw_line(from.x, from.y, to.x, to.y, thickness, color);
+    }
+
+
+    fn circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        draw_circle(center.x, center.y, radius, color);
+    }
+
+
+    fn circle_lines(&mut self, center: Vec2, radius: f32, thickness: f32, color: Color) {
+        draw_circle_lines(center.x, center.y, radius, thickness, color);
+    }
+
+
+    fn polygon(&mut self, points: &[Vec2], color: Color) {
+        for i in 1..points.len().saturating_sub(1) {
+            draw_triangle(points[0], points[i], points[i + 1], color);
+        }
+    }
+
+
+    fn polygon_lines(&mut self, points: &[Vec2], thickness: f32, color: Color) {
+        for i in 0..points.len() {
+            let (from, to) = (points[i], points[(i + 1) % points.len()]);
+            draw_line(from.x, from.y, to.x, to.y, thickness, color);
+        }
+    }
+
+
+    fn text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) {
+        draw_text(text, pos.x, pos.y, size, color);
+    }
+
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        measure_text(text, None, size as u16, 1.0).width
+    }
+
+
+    fn textured_triangle(&mut self, points: [Vec2; 3], uvs: [Vec2; 3], texture: &RenderTexture, color: Color) {
+        let vertices = (0..3).map(|i| Vertex::new(points[i].x, points[i].y, 0.0, uvs[i].x, uvs[i].y, color)).collect();
+        draw_mesh(&Mesh {
+            vertices,
+            indices: vec![0, 1, 2],
+            texture: Some(texture.texture().clone()),
+        });
+    }
+
+
+    // The ones of macroquad are faster
+    fn regular_polygon(&mut self, center: Vec2, sides: u8, radius: f32, rotation: f32, color: Color) {
+        draw_poly(center.x, center.y, sides, radius, rotation, color);
+    }
+
+
+    fn regular_polygon_lines(&mut self, center: Vec2, sides: u8, radius: f32, rotation: f32, thickness: f32, color: Color) {
+        draw_poly_lines(center.x, center.y, sides, radius, rotation, thickness, color);
+    }
+
+
+    fn rect(&mut self, corner: Vec2, size: Vec2, color: Color) {
+        draw_rectangle(corner.x, corner.y, size.x, size.y, color);
+    }
+
+
+    fn rect_lines(&mut self, corner: Vec2, size: Vec2, thickness: f32, color: Color) {
+        draw_rectangle_lines(corner.x, corner.y, size.x, size.y, thickness, color);
+    }
+}
@@ -0,0 +1,818 @@
+use std::{collections::VecDeque, fmt::{self, Debug, Write}, path::Path, str::{FromStr, SplitWhitespace}};
+
+use macroquad::{color::Color, math::Vec2};
+
+use super::{
+    grab::GrabTarget, kinematic::{Easing, KinematicPath, LoopMode, PathShape}, snapshot::TopologyEvent, Command, Explosion, Falloff,
+    GuardSettings, IKChain, Link, LinkRef, Point, RecoveryPolicy, SceneError, Simulation, SimulationState,
+};
+
+
+// Steps between the state hashes of a recording
+const HASH_INTERVAL: u64 = 24;
+const EASINGS: [Easing; 5] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::SineInOut];
+const LOOP_MODES: [LoopMode; 3] = [LoopMode::Once, LoopMode::Loop, LoopMode::PingPong];
+const FALLOFFS: [Falloff; 3] = [Falloff::Constant, Falloff::Linear, Falloff::Quadratic];
+const POLICIES: [RecoveryPolicy; 3] = [RecoveryPolicy::Rollback, RecoveryPolicy::Clamp, RecoveryPolicy::Report];
+
+
+/// A recording of everything that happened to the simulation: the state it started from, every command with
+/// the step it got applied before, and hashes of the state along the way. The recording runs in deterministic mode,
+/// so playing it back gives the exact same positions, and the hashes show the first step where it didnt.
+///
+/// Saved as text, one entry per line:
+/// ```text
+/// seed <seed>
+/// delta <time of a step>
+/// substeps <substeps> <time of a substep>
+/// time <simulated time>
+/// force <x> <y>
+/// bounds <width> <height>
+/// guard <policy> <max speed> <max energy growth> <adaptive (0 or 1)> <max substeps>
+/// point <x> <y> <mass> <r> <g> <b> <a> <fixed (0 or 1)> <path or -> <previous x> <previous y>
+/// link <from> <to> <min length> <max length> <stiffness> <damping> <max load>
+/// ik <target x> <target y> <error margin> <iterations> <max angle> <number of links> <links...>
+/// @<step> <command> <values...>
+/// hash <step> <hash>
+/// steps <number of recorded steps>
+/// ```
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// Not used by the simulation, it is kept for whatever generated the scene, so random scenes can be rebuilt
+    pub seed: u64,
+    // Time of every step
+    delta: f32,
+    substeps: usize,
+    step_delta: f32,
+    time: f32,
+    force: Vec2,
+    bounds: Vec2,
+    guard: GuardSettings,
+    // Points and their previous positions
+    points: Vec<(Point, Vec2)>,
+    links: Vec<Link>,
+    ik_chains: Vec<IKChain>,
+    // Commands as (step, command), the command got applied right before that step
+    commands: Vec<(u64, Command)>,
+    // Hashes of the state after the step, as (step, hash)
+    hashes: Vec<(u64, u64)>,
+    steps: u64,
+}
+
+
+/// The first recorded hash that didnt match while playing back a replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// The state was different after this step. It was the same HASH_INTERVAL steps before
+    pub step: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "diverged after step {}: expected hash {:016x}, found {:016x}", self.step, self.expected, self.actual)
+    }
+}
+
+
+// What happened to the recording or playback, sent to the render thread
+#[derive(Debug, Clone)]
+pub(super) enum ReplayEvent {
+    Recorded(Box<Replay>),
+    Diverged(Divergence),
+    Finished,
+}
+
+
+// Feeds the commands of a replay back into the state and compares the hashes
+#[derive(Debug, Clone)]
+pub(super) struct ReplayPlayer {
+    commands: VecDeque<(u64, Command)>,
+    hashes: VecDeque<(u64, u64)>,
+    steps: u64,
+    diverged: bool,
+}
+
+
+impl Replay {
+    /// Number of recorded steps
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+
+    /// Time of every step
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+
+    pub(super) fn force(&self) -> Vec2 {
+        self.force
+    }
+
+
+    pub(super) fn bounds(&self) -> Vec2 {
+        self.bounds
+    }
+
+
+    pub(super) fn guard(&self) -> GuardSettings {
+        self.guard
+    }
+
+
+    pub(super) fn num_points(&self) -> usize {
+        self.points.len()
+    }
+
+
+    pub(super) fn record_command(&mut self, step: u64, command: Command) {
+        self.commands.push((step, command));
+    }
+
+
+    // Everything about the state that isnt rebuilt from the points and links
+    fn capture(state: &SimulationState, seed: u64, delta: f32) -> Self {
+        let points = (0..state.positions.len()).map(|i| {
+            let mut point = Point::new(state.positions[i]).mass(state.masses[i]).color(state.colors[i]);
+            point.fixed = state.fixed[i];
+            point.path = state.kinematic_paths.iter().find(|(idx, _)| *idx == i).map(|(_, path)| path.clone());
+            (point, state.prev_positions[i])
+        }).collect();
+        // Grabs and impulses dont belong to the scene, they are replayed as commands before the first step
+        let mut commands = vec![];
+        if !state.grabbed.is_empty() {
+            commands.push((0, Command::SetGrab(state.grabbed.clone(), state.grab_strength)));
+        }
+        commands.extend(state.impulses.iter().map(|(idx, impulse)| (0, Command::ApplyImpulse(*idx, *impulse))));
+        Self {
+            seed,
+            delta,
+            substeps: state.guard.substeps,
+            step_delta: state.step_delta,
+            time: state.time,
+            force: state.force,
+            bounds: state.bounds,
+            guard: state.guard.settings,
+            points,
+            links: state.links.clone(),
+            ik_chains: state.ik_chains.clone(),
+            commands,
+            hashes: vec![],
+            steps: 0,
+        }
+    }
+
+
+    // A new state at the start of the replay. Its events replace the links of the render snapshot
+    fn build_state(&self) -> SimulationState {
+        let mut state = SimulationState::new();
+        state.time = self.time;
+        state.force = self.force;
+        state.bounds = self.bounds;
+        state.guard.settings = self.guard;
+        state.guard.substeps = self.substeps;
+        state.step_delta = self.step_delta;
+        state.fixed_delta = Some(self.delta);
+        state.add_points(&self.points.iter().map(|(point, _)| point.clone()).collect::<Vec<_>>());
+        for (i, (_, prev_pos)) in self.points.iter().enumerate() {
+            state.prev_positions[i] = *prev_pos;
+        }
+        for link in self.links.iter() {
+            state.add_link(link.clone());
+        }
+        for chain in self.ik_chains.iter() {
+            state.add_ik_chain(chain.clone());
+        }
+        state.topology_events.clear();
+        state.topology_events.push(TopologyEvent::Reset(state.links.clone(), state.islands.clone()));
+        state
+    }
+
+
+    /// Plays the whole replay back without drawing it, and returns the first step where it went differently
+    pub fn verify(&self) -> Result<(), Divergence> {
+        let mut state = self.build_state();
+        state.player = Some(Box::new(ReplayPlayer::new(self)));
+        while state.player.is_some() {
+            Simulation::update_state(&mut state, self.delta);
+        }
+        state.replay_events.iter().find_map(|event| match event {
+            ReplayEvent::Diverged(divergence) => Some(Err(*divergence)),
+            _ => None,
+        }).unwrap_or(Ok(()))
+    }
+
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# verlet replay\n");
+        let _ = writeln!(text, "seed {}", self.seed);
+        let _ = writeln!(text, "delta {}", self.delta);
+        let _ = writeln!(text, "substeps {} {}", self.substeps, self.step_delta);
+        let _ = writeln!(text, "time {}", self.time);
+        let _ = writeln!(text, "force {} {}", self.force.x, self.force.y);
+        let _ = writeln!(text, "bounds {} {}", self.bounds.x, self.bounds.y);
+        let guard = &self.guard;
+        let _ = writeln!(text, "guard {:?} {} {} {} {}", guard.policy, guard.max_speed, guard.max_energy_growth, guard.adaptive_substeps as u8, guard.max_substeps);
+        for (point, prev_pos) in self.points.iter() {
+            text.push_str("point");
+            write_point(&mut text, point);
+            let _ = writeln!(text, " {} {}", prev_pos.x, prev_pos.y);
+        }
+        for link in self.links.iter() {
+            text.push_str("link");
+            write_link(&mut text, link);
+            text.push('\n');
+        }
+        for chain in self.ik_chains.iter() {
+            text.push_str("ik");
+            write_ik_chain(&mut text, chain);
+            text.push('\n');
+        }
+        for (step, command) in self.commands.iter() {
+            let _ = write!(text, "@{}", step);
+            write_command(&mut text, command);
+            text.push('\n');
+        }
+        for (step, hash) in self.hashes.iter() {
+            let _ = writeln!(text, "hash {} {:016x}", step, hash);
+        }
+        let _ = writeln!(text, "steps {}", self.steps);
+        text
+    }
+
+
+    pub fn parse(text: &str) -> Result<Self, SceneError> {
+        let mut replay = Replay {
+            seed: 0,
+            delta: 1.0 / Simulation::THREAD_STEPS_PER_SECOND,
+            substeps: 1,
+            step_delta: 1.0 / Simulation::THREAD_STEPS_PER_SECOND,
+            time: 0.0,
+            force: Simulation::GRAVITY,
+            bounds: Simulation::DEFAULT_BOUNDS,
+            guard: GuardSettings::default(),
+            points: vec![],
+            links: vec![],
+            ik_chains: vec![],
+            commands: vec![],
+            hashes: vec![],
+            steps: 0,
+        };
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut values = Values(line.split_whitespace());
+            let kind = values.0.next().unwrap_or_default();
+            let mut parse_entry = || -> Result<(), String> {
+                match kind {
+                    "seed" => replay.seed = values.next()?,
+                    "delta" => replay.delta = values.next()?,
+                    "substeps" => {
+                        replay.substeps = values.next()?;
+                        replay.step_delta = values.next()?;
+                    },
+                    "time" => replay.time = values.next()?,
+                    "force" => replay.force = values.vec2()?,
+                    "bounds" => replay.bounds = values.vec2()?,
+                    "guard" => replay.guard = GuardSettings {
+                        policy: values.named(&POLICIES)?,
+                        max_speed: values.next()?,
+                        max_energy_growth: values.next()?,
+                        adaptive_substeps: values.bool()?,
+                        max_substeps: values.next()?,
+                    },
+                    "point" => {
+                        let point = values.point()?;
+                        replay.points.push((point, values.vec2()?));
+                    },
+                    "link" => replay.links.push(values.link()?),
+                    "ik" => replay.ik_chains.push(values.ik_chain()?),
+                    "hash" => {
+                        let step = values.next()?;
+                        let hash = values.0.next().ok_or("missing hash")?;
+                        let hash = u64::from_str_radix(hash, 16).map_err(|_| format!("\"{}\" is not a hash", hash))?;
+                        replay.hashes.push((step, hash));
+                    },
+                    "steps" => replay.steps = values.next()?,
+                    _ => match kind.strip_prefix('@') {
+                        Some(step) => {
+                            let step = step.parse().map_err(|_| format!("\"{}\" is not a step", step))?;
+                            replay.commands.push((step, values.command()?));
+                        },
+                        None => return Err(format!("unknown entry \"{}\"", kind)),
+                    },
+                }
+                match values.0.next() {
+                    Some(value) => Err(format!("unexpected value \"{}\"", value)),
+                    None => Ok(()),
+                }
+            };
+            parse_entry().map_err(|message| SceneError::Parse { line: line_idx + 1, message })?;
+        }
+        Ok(replay)
+    }
+
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Replay::parse(&std::fs::read_to_string(path)?)
+    }
+
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        Ok(std::fs::write(path, self.to_text())?)
+    }
+}
+
+
+impl ReplayPlayer {
+    fn new(replay: &Replay) -> Self {
+        Self {
+            commands: replay.commands.iter().cloned().collect(),
+            hashes: replay.hashes.iter().copied().collect(),
+            steps: replay.steps,
+            diverged: false,
+        }
+    }
+}
+
+
+impl SimulationState {
+    // Hash of the points and links, to compare two runs
+    pub(super) fn hash(&self) -> u64 {
+        // FNV-1a
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut add = |bits: u32| {
+            for byte in bits.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        for ((pos, prev_pos), fixed) in self.positions.iter().zip(self.prev_positions.iter()).zip(self.fixed.iter()) {
+            for value in [pos.x, pos.y, prev_pos.x, prev_pos.y] {
+                add(value.to_bits());
+            }
+            add(*fixed as u32);
+        }
+        // Links can change without moving any point right away
+        add(self.links.len() as u32);
+        for link in self.links.iter() {
+            add(link.from_idx as u32);
+            add(link.to_idx as u32);
+            for value in [link.min_length, link.max_length, link.stiffness, link.damping, link.max_stress] {
+                add(value.to_bits());
+            }
+        }
+        hash
+    }
+
+
+    // Starts over from what the replay will start from, so the recording and its playback start out the same
+    pub(super) fn start_recording(&mut self, seed: u64) {
+        let delta = self.fixed_delta.unwrap_or(1.0 / Simulation::THREAD_STEPS_PER_SECOND);
+        let replay = Replay::capture(self, seed, delta);
+        let mut events = std::mem::take(&mut self.topology_events);
+        let replay_events = std::mem::take(&mut self.replay_events);
+        let samples = std::mem::take(&mut self.energy_samples);
+        let link_breaks = std::mem::take(&mut self.link_breaks);
+        let measure_energy = self.measure_energy;
+        *self = replay.build_state();
+        events.append(&mut self.topology_events);
+        self.topology_events = events;
+        self.replay_events = replay_events;
+        self.energy_samples = samples;
+        self.link_breaks = link_breaks;
+        self.measure_energy = measure_energy;
+        self.recording = Some(Box::new(replay));
+    }
+
+
+    pub(super) fn stop_recording(&mut self) {
+        let Some(mut replay) = self.recording.take() else {
+            return;
+        };
+        if replay.hashes.last().is_none_or(|(step, _)| *step != self.steps) {
+            replay.hashes.push((self.steps, self.hash()));
+        }
+        replay.steps = self.steps;
+        self.replay_events.push(ReplayEvent::Recorded(replay));
+    }
+
+
+    pub(super) fn play_replay(&mut self, replay: &Replay) {
+        let mut events = std::mem::take(&mut self.topology_events);
+        let replay_events = std::mem::take(&mut self.replay_events);
+        let measure_energy = self.measure_energy;
+        *self = replay.build_state();
+        events.append(&mut self.topology_events);
+        self.topology_events = events;
+        self.replay_events = replay_events;
+        self.measure_energy = measure_energy;
+        self.player = Some(Box::new(ReplayPlayer::new(replay)));
+    }
+
+
+    // Commands that go before the next step
+    pub(super) fn take_replay_commands(&mut self) -> Vec<Command> {
+        let Some(player) = &mut self.player else {
+            return vec![];
+        };
+        let mut commands = vec![];
+        while player.commands.front().is_some_and(|(step, _)| *step <= self.steps) {
+            commands.extend(player.commands.pop_front().map(|(_, command)| command));
+        }
+        commands
+    }
+
+
+    // Records or checks the hash of the state after a step
+    pub(super) fn after_replay_step(&mut self) {
+        let steps = self.steps;
+        let record = self.recording.is_some() && steps.is_multiple_of(HASH_INTERVAL);
+        let check = self.player.as_ref().is_some_and(|player| player.hashes.iter().any(|(step, _)| *step == steps));
+        let hash = (record || check).then(|| self.hash());
+        if let (Some(replay), Some(hash)) = (&mut self.recording, hash) {
+            replay.hashes.push((steps, hash));
+        }
+        let Some(player) = &mut self.player else {
+            return;
+        };
+        let mut diverged = None;
+        while let Some((step, expected)) = player.hashes.front().copied().filter(|(step, _)| *step <= steps) {
+            player.hashes.pop_front();
+            let actual = hash.unwrap_or_default();
+            if step == steps && actual != expected && !player.diverged {
+                player.diverged = true;
+                diverged = Some(Divergence { step, expected, actual });
+            }
+        }
+        let finished = steps >= player.steps;
+        if finished {
+            self.player = None;
+        }
+        self.replay_events.extend(diverged.map(ReplayEvent::Diverged));
+        if finished {
+            self.replay_events.push(ReplayEvent::Finished);
+        }
+    }
+}
+
+
+impl Command {
+    // Commands that dont change the simulated state dont need to be replayed
+    pub(super) fn is_recorded(&self) -> bool {
+        !matches!(self, Command::SetPaused(_) | Command::SetMeasureEnergy(_) | Command::SetDeterministic(_) | Command::StartRecording(_) | Command::StopRecording | Command::PlayReplay(_))
+    }
+}
+
+
+fn write_point(text: &mut String, point: &Point) {
+    let Color { r, g, b, a } = point.color;
+    let _ = write!(text, " {} {} {} {} {} {} {} {}", point.position.x, point.position.y, point.mass, r, g, b, a, point.fixed as u8);
+    let Some(path) = &point.path else {
+        text.push_str(" -");
+        return;
+    };
+    let _ = match &path.shape {
+        PathShape::Line { from, to } => write!(text, " line {} {} {} {}", from.x, from.y, to.x, to.y),
+        PathShape::Circle { center, radius, start_angle } => write!(text, " circle {} {} {} {}", center.x, center.y, radius, start_angle),
+        PathShape::Bezier { p0, p1, p2, p3 } => write!(text, " bezier {} {} {} {} {} {} {} {}", p0.x, p0.y, p1.x, p1.y, p2.x, p2.y, p3.x, p3.y),
+        PathShape::Keyframes(frames) => {
+            let _ = write!(text, " keyframes {}", frames.len());
+            for (time, pos) in frames.iter() {
+                let _ = write!(text, " {} {} {}", time, pos.x, pos.y);
+            }
+            Ok(())
+        },
+    };
+    let _ = write!(text, " {} {:?} {:?} {}", path.duration, path.easing, path.looping, path.time_offset);
+}
+
+
+fn write_link(text: &mut String, link: &Link) {
+    let _ = write!(text, " {} {} {} {} {} {} {}", link.from_idx, link.to_idx, link.min_length, link.max_length, link.stiffness, link.damping, link.max_stress);
+}
+
+
+fn write_ik_chain(text: &mut String, chain: &IKChain) {
+    let target = chain.target_position;
+    let _ = write!(text, " {} {} {} {} {} {}", target.x, target.y, chain.error_margin, chain.num_iterations, chain.max_angle_per_link, chain.links.len());
+    for link_idx in chain.links.iter() {
+        let _ = write!(text, " {}", link_idx);
+    }
+}
+
+
+fn write_link_ref(text: &mut String, link_ref: &LinkRef) {
+    let _ = write!(text, " {} {} {}", link_ref.idx, link_ref.from_idx, link_ref.to_idx);
+}
+
+
+fn write_command(text: &mut String, command: &Command) {
+    let _ = match command {
+        Command::AddPoints(points) => {
+            let _ = write!(text, " add_points {}", points.len());
+            for point in points.iter() {
+                write_point(text, point);
+            }
+            Ok(())
+        },
+        Command::RemovePoints(points) => {
+            let _ = write!(text, " remove_points {}", points.len());
+            for idx in points.iter() {
+                let _ = write!(text, " {}", idx);
+            }
+            Ok(())
+        },
+        Command::AddLink(link) => {
+            text.push_str(" add_link");
+            write_link(text, link);
+            Ok(())
+        },
+        Command::AddIKChain(chain) => {
+            text.push_str(" add_ik_chain");
+            write_ik_chain(text, chain);
+            Ok(())
+        },
+        Command::SetBounds(bounds) => write!(text, " set_bounds {} {}", bounds.x, bounds.y),
+        Command::SetForce(force) => write!(text, " set_force {} {}", force.x, force.y),
+        Command::MovePoint(idx, pos) => write!(text, " move_point {} {} {}", idx, pos.x, pos.y),
+        Command::SetPointColor(idx, Color { r, g, b, a }) => write!(text, " set_point_color {} {} {} {} {}", idx, r, g, b, a),
+        Command::SetFixed(idx, fixed) => write!(text, " set_fixed {} {}", idx, *fixed as u8),
+        Command::SetMass(idx, mass) => write!(text, " set_mass {} {}", idx, mass),
+        Command::SetVelocity(idx, velocity) => write!(text, " set_velocity {} {} {}", idx, velocity.x, velocity.y),
+        Command::SetLinkParams(link_ref, link) => {
+            text.push_str(" set_link_params");
+            write_link_ref(text, link_ref);
+            write_link(text, link);
+            Ok(())
+        },
+        Command::CutLinks(link_refs) => {
+            let _ = write!(text, " cut_links {}", link_refs.len());
+            for link_ref in link_refs.iter() {
+                write_link_ref(text, link_ref);
+            }
+            Ok(())
+        },
+        Command::SetIKTarget(idx, pos) => write!(text, " set_ik_target {} {} {}", idx, pos.x, pos.y),
+        Command::SetGrab(targets, strength) => {
+            let _ = write!(text, " set_grab {} {}", strength, targets.len());
+            for target in targets.iter() {
+                let _ = write!(text, " {} {} {} {} {} {}", target.point_idx, target.offset.x, target.offset.y, target.weight, target.target.x, target.target.y);
+            }
+            Ok(())
+        },
+        Command::ApplyImpulse(idx, impulse) => write!(text, " apply_impulse {} {} {}", idx, impulse.x, impulse.y),
+        Command::Explode(explosion) => {
+            let _ = write!(text, " explode {} {} {} {} {:?}", explosion.center.x, explosion.center.y, explosion.radius, explosion.strength, explosion.falloff);
+            let _ = match explosion.direction {
+                Some(direction) => write!(text, " {} {}", direction.x, direction.y),
+                None => write!(text, " -"),
+            };
+            match explosion.break_threshold {
+                Some(threshold) => write!(text, " {}", threshold),
+                None => write!(text, " -"),
+            }
+        },
+        Command::SetGuard(guard) => {
+            write!(text, " set_guard {:?} {} {} {} {}", guard.policy, guard.max_speed, guard.max_energy_growth, guard.adaptive_substeps as u8, guard.max_substeps)
+        },
+        // Not recorded, see Command::is_recorded
+        Command::SetPaused(_) | Command::SetMeasureEnergy(_) | Command::SetDeterministic(_) | Command::StartRecording(_) | Command::StopRecording | Command::PlayReplay(_) => Ok(()),
+    };
+}
+
+
+// The values of one line, read one after another
+struct Values<'a>(SplitWhitespace<'a>);
+impl Values<'_> {
+    fn next<T: FromStr>(&mut self) -> Result<T, String> {
+        let text = self.0.next().ok_or("missing value")?;
+        text.parse().map_err(|_| format!("\"{}\" is not a valid value", text))
+    }
+
+
+    fn vec2(&mut self) -> Result<Vec2, String> {
+        Ok(Vec2::new(self.next()?, self.next()?))
+    }
+
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.next::<u8>()? != 0)
+    }
+
+
+    // One of the values, by its Debug name
+    fn named<T: Debug + Copy>(&mut self, values: &[T]) -> Result<T, String> {
+        let text = self.0.next().ok_or("missing value")?;
+        values.iter().find(|value| format!("{:?}", value) == text).copied().ok_or_else(|| format!("unknown value \"{}\"", text))
+    }
+
+
+    // Either "-" or a value
+    fn optional<T>(&mut self, mut parse: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Option<T>, String> {
+        let mut peek = self.0.clone();
+        if peek.next() == Some("-") {
+            self.0 = peek;
+            return Ok(None);
+        }
+        parse(self).map(Some)
+    }
+
+
+    fn list<T>(&mut self, mut parse: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let len = self.next::<usize>()?;
+        (0..len).map(|_| parse(self)).collect()
+    }
+
+
+    fn point(&mut self) -> Result<Point, String> {
+        let mut point = Point::new(self.vec2()?).mass(self.next()?).color(Color::new(self.next()?, self.next()?, self.next()?, self.next()?));
+        point.fixed = self.bool()?;
+        point.path = self.optional(|values| {
+            let kind = values.0.next().ok_or("missing path")?;
+            let shape = match kind {
+                "line" => PathShape::Line { from: values.vec2()?, to: values.vec2()? },
+                "circle" => PathShape::Circle { center: values.vec2()?, radius: values.next()?, start_angle: values.next()? },
+                "bezier" => PathShape::Bezier { p0: values.vec2()?, p1: values.vec2()?, p2: values.vec2()?, p3: values.vec2()? },
+                "keyframes" => PathShape::Keyframes(values.list(|values| Ok((values.next()?, values.vec2()?)))?),
+                _ => return Err(format!("unknown path \"{}\"", kind)),
+            };
+            let mut path = KinematicPath::new(shape, values.next()?);
+            path.easing = values.named(&EASINGS)?;
+            path.looping = values.named(&LOOP_MODES)?;
+            path.time_offset = values.next()?;
+            Ok(path)
+        })?;
+        Ok(point)
+    }
+
+
+    fn link(&mut self) -> Result<Link, String> {
+        Ok(Link::new(self.next()?, self.next()?)
+            .min_length(self.next()?)
+            .max_length(self.next()?)
+            .stiffness(self.next()?)
+            .damping(self.next()?)
+            .max_stress(self.next()?))
+    }
+
+
+    fn ik_chain(&mut self) -> Result<IKChain, String> {
+        let target = self.vec2()?;
+        let mut chain = IKChain::new(vec![]).error_margin(self.next()?).iterations(self.next()?).max_angle_per_link(self.next()?);
+        chain.links = self.list(|values| values.next())?;
+        chain.target_position = target;
+        Ok(chain)
+    }
+
+
+    fn link_ref(&mut self) -> Result<LinkRef, String> {
+        Ok(LinkRef { idx: self.next()?, from_idx: self.next()?, to_idx: self.next()? })
+    }
+
+
+    fn command(&mut self) -> Result<Command, String> {
+        let kind = self.0.next().ok_or("missing command")?;
+        Ok(match kind {
+            "add_points" => Command::AddPoints(self.list(|values| values.point())?),
+            "remove_points" => Command::RemovePoints(self.list(|values| values.next())?),
+            "add_link" => Command::AddLink(self.link()?),
+            "add_ik_chain" => Command::AddIKChain(self.ik_chain()?),
+            "set_bounds" => Command::SetBounds(self.vec2()?),
+            "set_force" => Command::SetForce(self.vec2()?),
+            "move_point" => Command::MovePoint(self.next()?, self.vec2()?),
+            "set_point_color" => Command::SetPointColor(self.next()?, Color::new(self.next()?, self.next()?, self.next()?, self.next()?)),
+            "set_fixed" => Command::SetFixed(self.next()?, self.bool()?),
+            "set_mass" => Command::SetMass(self.next()?, self.next()?),
+            "set_velocity" => Command::SetVelocity(self.next()?, self.vec2()?),
+            "set_link_params" => Command::SetLinkParams(self.link_ref()?, self.link()?),
+            "cut_links" => Command::CutLinks(self.list(|values| values.link_ref())?),
+            "set_ik_target" => Command::SetIKTarget(self.next()?, self.vec2()?),
+            "set_grab" => {
+                let strength = self.next()?;
+                let targets = self.list(|values| {
+                    let mut target = GrabTarget::new(values.next()?, values.vec2()?, values.next()?);
+                    target.target = values.vec2()?;
+                    Ok(target)
+                })?;
+                Command::SetGrab(targets, strength)
+            },
+            "apply_impulse" => Command::ApplyImpulse(self.next()?, self.vec2()?),
+            "explode" => {
+                let mut explosion = Explosion::new(self.vec2()?, self.next()?, self.next()?).falloff(self.named(&FALLOFFS)?);
+                explosion.direction = self.optional(|values| values.vec2())?;
+                explosion.break_threshold = self.optional(|values| values.next())?;
+                Command::Explode(explosion)
+            },
+            "set_guard" => Command::SetGuard(GuardSettings {
+                policy: self.named(&POLICIES)?,
+                max_speed: self.next()?,
+                max_energy_growth: self.next()?,
+                adaptive_substeps: self.bool()?,
+                max_substeps: self.next()?,
+            }),
+            _ => return Err(format!("unknown command \"{}\"", kind)),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::{Divergence, Replay};
+    use crate::simulation::{grab::GrabTarget, Command, Explosion, KinematicPath, Link, Point, Simulation};
+
+
+    const DELTA: f32 = 1.0 / 240.0;
+    // Step before which the explosion gets applied
+    const EXPLOSION_STEP: usize = 10;
+
+
+    // Two ropes, one swung around by a keyframed path, hit by an explosion and grabbed while recording
+    fn record() -> Replay {
+        let mut simulation = Simulation::new();
+        simulation.set_deterministic(Some(DELTA));
+        let path = KinematicPath::keyframes(vec![(0.0, Vec2::new(4.0, 2.0)), (0.5, Vec2::new(5.0, 2.0)), (1.0, Vec2::new(5.0, 3.0))]);
+        simulation.add_points(&[
+            Point::new(Vec2::new(4.0, 2.0)).kinematic(path),
+            Point::new(Vec2::new(4.0, 3.0)),
+            Point::new(Vec2::new(4.0, 4.0)),
+            Point::new(Vec2::new(6.0, 2.0)).fixed(),
+            Point::new(Vec2::new(6.0, 3.0)),
+        ]);
+        for (from_idx, to_idx) in [(0, 1), (1, 2), (3, 4)] {
+            simulation.add_link(Link::new(from_idx, to_idx));
+        }
+        simulation.step_headless(DELTA);
+
+        simulation.start_recording(7);
+        for step in 0..100 {
+            match step {
+                EXPLOSION_STEP => simulation.push_command(Command::Explode(Explosion::new(Vec2::new(4.5, 3.5), 2.0, 5.0))),
+                30 => {
+                    let mut target = GrabTarget::new(4, Vec2::ZERO, 1.0);
+                    target.follow(Vec2::new(7.0, 3.0));
+                    simulation.push_command(Command::SetGrab(vec![target], 0.5));
+                },
+                60 => simulation.push_command(Command::SetGrab(vec![], 0.0)),
+                _ => (),
+            }
+            simulation.step_headless(DELTA);
+        }
+        simulation.stop_recording();
+        simulation.step_headless(DELTA);
+        simulation.take_recording().expect("the recording got stopped")
+    }
+
+
+    #[test]
+    fn replay_survives_text_and_plays_back_the_same() {
+        let replay = record();
+        let text = replay.to_text();
+        assert!(text.contains(" keyframes "));
+        assert!(text.lines().any(|line| line.contains(" explode ") && line.ends_with(" - -")));
+        assert!(text.contains(" set_grab "));
+
+        let parsed = Replay::parse(&text).unwrap();
+        assert_eq!(parsed.to_text(), text);
+        assert_eq!(parsed.steps(), 100);
+        assert_eq!(parsed.verify(), Ok(()));
+    }
+
+
+    #[test]
+    fn changed_hash_diverges_at_its_step() {
+        let mut replay = record();
+        let (step, actual) = replay.hashes[1];
+        replay.hashes[1].1 = actual ^ 1;
+        assert_eq!(replay.verify(), Err(Divergence { step, expected: actual ^ 1, actual }));
+    }
+
+
+    #[test]
+    fn changed_command_diverges_at_the_next_hash() {
+        let mut replay = record();
+        let (command_step, command) = replay.commands.iter_mut().find(|(_, command)| matches!(command, Command::Explode(_))).unwrap();
+        *command = Command::Explode(Explosion::new(Vec2::new(4.5, 3.5), 2.0, 10.0));
+        // The command goes before its step, so the state after that step is the first one to differ
+        let command_step = *command_step;
+        let step = replay.hashes.iter().map(|(step, _)| *step).find(|step| *step > command_step).unwrap();
+        assert_eq!(replay.verify().map_err(|divergence| divergence.step), Err(step));
+    }
+
+
+    #[test]
+    fn changed_link_diverges_before_anything_moves_differently() {
+        let mut replay = record();
+        // None of the links come close to breaking, so this doesnt change any position
+        replay.links[2].max_stress *= 2.0;
+        let step = replay.hashes[0].0;
+        assert_eq!(replay.verify().map_err(|divergence| divergence.step), Err(step));
+    }
+}
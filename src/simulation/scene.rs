@@ -0,0 +1,163 @@
+use std::{fmt::{self, Write}, path::Path, str::FromStr};
+
+use macroquad::{color::Color, math::Vec2};
+
+use super::{Camera, Link, Point};
+
+
+/// Points and links that can be saved to and loaded from a text file, so scenes can be built without touching code.
+///
+/// Every line holds one entry, with its values separated by spaces:
+/// ```text
+/// point <x> <y> <mass> <r> <g> <b> <a> <fixed (0 or 1)>
+/// link <from> <to> <min length> <max length> <stiffness> <damping> [max load]
+/// bounds <width> <height>
+/// scale <pixels per meter>
+/// units <m, cm, mm or px>
+/// ```
+/// The points are numbered in the order they appear in. Empty lines and lines starting with `#` are skipped.
+/// Masses are in kg and loads in N. Positions and lengths are in meters, unless a `units` line changes
+/// the unit of the entries after it. `px` are pixels at the scale of the scene.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub points: Vec<Point>,
+    pub links: Vec<Link>,
+    /// World area the points are kept in, the current one is kept if there is none
+    pub bounds: Option<Vec2>,
+    /// Rendering scale, the current one is kept if there is none
+    pub pixels_per_meter: Option<f32>,
+}
+impl Scene {
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# verlet scene\n");
+        if let Some(pixels_per_meter) = self.pixels_per_meter {
+            let _ = writeln!(text, "scale {}", pixels_per_meter);
+        }
+        if let Some(bounds) = self.bounds {
+            let _ = writeln!(text, "bounds {} {}", bounds.x, bounds.y);
+        }
+        for point in self.points.iter() {
+            let Color { r, g, b, a } = point.color;
+            let _ = writeln!(text, "point {} {} {} {} {} {} {} {}", point.position.x, point.position.y, point.mass, r, g, b, a, point.fixed as u8);
+        }
+        for link in self.links.iter() {
+            let _ = writeln!(text, "link {} {} {} {} {} {} {}", link.from_idx, link.to_idx, link.min_length, link.max_length, link.stiffness, link.damping, link.max_stress);
+        }
+        text
+    }
+
+
+    pub fn parse(text: &str) -> Result<Self, SceneError> {
+        let mut scene = Scene::default();
+        // Meters per unit of the positions and lengths
+        let mut unit = 1.0;
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| SceneError::Parse { line: line_idx + 1, message };
+            let mut values = line.split_whitespace();
+            let kind = values.next().unwrap_or_default();
+            let values = values.collect::<Vec<_>>();
+            match kind {
+                "point" => {
+                    let [x, y, mass, r, g, b, a, fixed] = parse_values::<f32, 8>(&values).map_err(error)?;
+                    let mut point = Point::new(Vec2::new(x, y) * unit).mass(mass).color(Color::new(r, g, b, a));
+                    if fixed != 0.0 {
+                        point = point.fixed();
+                    }
+                    point.validate().map_err(|err| error(err.to_string()))?;
+                    scene.points.push(point);
+                },
+                "link" => {
+                    // The max stress is optional
+                    if values.len() != 6 && values.len() != 7 {
+                        return Err(error(format!("expected 6 or 7 values, found {}", values.len())));
+                    }
+                    let [from_idx, to_idx] = parse_values::<usize, 2>(&values[..2]).map_err(error)?;
+                    let [min_length, max_length, stiffness, damping] = parse_values::<f32, 4>(&values[2..6]).map_err(error)?;
+                    let mut link = Link::new(from_idx, to_idx).min_length(min_length * unit).max_length(max_length * unit).stiffness(stiffness).damping(damping);
+                    if let Some(max_stress) = values.get(6) {
+                        let [max_stress] = parse_values::<f32, 1>(&[max_stress]).map_err(error)?;
+                        link = link.max_stress(max_stress);
+                    }
+                    // Links can only refer to the points before them
+                    link.validate(scene.points.len()).map_err(|err| error(err.to_string()))?;
+                    scene.links.push(link);
+                },
+                "bounds" => {
+                    let [width, height] = parse_values::<f32, 2>(&values).map_err(error)?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err(error(format!("bounds can't be negative, found {} {}", width, height)));
+                    }
+                    scene.bounds = Some(Vec2::new(width, height) * unit);
+                },
+                "scale" => {
+                    let [pixels_per_meter] = parse_values::<f32, 1>(&values).map_err(error)?;
+                    if pixels_per_meter <= 0.0 {
+                        return Err(error(format!("scale has to be positive, found {}", pixels_per_meter)));
+                    }
+                    scene.pixels_per_meter = Some(pixels_per_meter);
+                },
+                "units" => {
+                    unit = match values.as_slice() {
+                        ["m"] => 1.0,
+                        ["cm"] => 0.01,
+                        ["mm"] => 0.001,
+                        ["px"] => 1.0 / scene.pixels_per_meter.unwrap_or(Camera::DEFAULT_PIXELS_PER_METER),
+                        _ => return Err(error(format!("unknown units \"{}\", expected m, cm, mm or px", values.join(" ")))),
+                    };
+                },
+                _ => return Err(error(format!("unknown entry \"{}\"", kind))),
+            }
+        }
+        Ok(scene)
+    }
+
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Scene::parse(&std::fs::read_to_string(path)?)
+    }
+
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        Ok(std::fs::write(path, self.to_text())?)
+    }
+}
+
+
+fn parse_values<T: FromStr + Default + Copy, const N: usize>(values: &[&str]) -> Result<[T; N], String> {
+    if values.len() != N {
+        return Err(format!("expected {} values, found {}", N, values.len()));
+    }
+    let mut parsed = [T::default(); N];
+    for (value, text) in parsed.iter_mut().zip(values) {
+        *value = text.parse().map_err(|_| format!("\"{}\" is not a number", text))?;
+    }
+    Ok(parsed)
+}
+
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+impl std::error::Error for SceneError {}
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
@@ -0,0 +1,360 @@
+use macroquad::{prelude::*, ui::{self, hash}};
+
+use crate::ui::colorbox;
+use super::{remap_index, Command, InputState, Link, LinkRef, Renderer, Simulation, Tool, POINT_RADIUS, SELECT_COLOR, SELECT_GRACE};
+
+
+// Minimum distance (in screen pixels) between two corners of the lasso
+const LASSO_SPACING: f32 = 5.0;
+
+
+// What dragging over empty space with the select tool does
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SelectShape {
+    // Selects everything inside of the dragged rectangle
+    Box,
+    // Selects everything inside of the drawn outline
+    Lasso,
+}
+
+
+// How a new selection gets combined with the current one
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) enum SelectMode {
+    Replace,
+    // Shift
+    Add,
+    // Ctrl
+    Remove,
+}
+impl SelectMode {
+    fn from_input(input: &InputState) -> Self {
+        if input.shift() {
+            SelectMode::Add
+        } else if input.ctrl() {
+            SelectMode::Remove
+        } else {
+            SelectMode::Replace
+        }
+    }
+}
+
+
+// The selected points and links, both sorted by index
+#[derive(Debug, Clone, Default)]
+pub(super) struct Selection {
+    pub(super) points: Vec<usize>,
+    pub(super) links: Vec<usize>,
+}
+impl Selection {
+    pub(super) fn is_empty(&self) -> bool {
+        self.points.is_empty() && self.links.is_empty()
+    }
+
+
+    pub(super) fn clear(&mut self) {
+        self.points.clear();
+        self.links.clear();
+    }
+
+
+    pub(super) fn has_point(&self, point_idx: usize) -> bool {
+        self.points.binary_search(&point_idx).is_ok()
+    }
+
+
+    pub(super) fn has_link(&self, link_idx: usize) -> bool {
+        self.links.binary_search(&link_idx).is_ok()
+    }
+
+
+    // The point, if it is the only thing selected
+    pub(super) fn single_point(&self) -> Option<usize> {
+        (self.points.len() == 1 && self.links.is_empty()).then(|| self.points[0])
+    }
+
+
+    // The link, if it is the only thing selected
+    pub(super) fn single_link(&self) -> Option<usize> {
+        (self.links.len() == 1 && self.points.is_empty()).then(|| self.links[0])
+    }
+
+
+    // Both lists have to be sorted
+    fn combine(&mut self, points: Vec<usize>, links: Vec<usize>, mode: SelectMode) {
+        match mode {
+            SelectMode::Replace => {
+                self.points = points;
+                self.links = links;
+            },
+            SelectMode::Add => {
+                self.points.extend(points);
+                self.points.sort_unstable();
+                self.points.dedup();
+                self.links.extend(links);
+                self.links.sort_unstable();
+                self.links.dedup();
+            },
+            SelectMode::Remove => {
+                self.points.retain(|idx| points.binary_search(idx).is_err());
+                self.links.retain(|idx| links.binary_search(idx).is_err());
+            },
+        }
+    }
+
+
+    // Points that move when the selection gets dragged, which includes the ends of the selected links
+    fn moved_points(&self, links: &[Link]) -> Vec<usize> {
+        let mut points = self.points.clone();
+        points.extend(self.links.iter().flat_map(|idx| [links[*idx].from_idx, links[*idx].to_idx]));
+        points.sort_unstable();
+        points.dedup();
+        points
+    }
+
+
+    // Keeps the link indices valid after the links were removed
+    pub(super) fn remap_links(&mut self, removed: &[usize]) {
+        self.links = self.links.iter().filter_map(|idx| remap_index(*idx, removed)).collect();
+    }
+
+
+    pub(super) fn remap_points(&mut self, removed: &[usize]) {
+        self.points = self.points.iter().filter_map(|idx| remap_index(*idx, removed)).collect();
+    }
+}
+
+
+// What the left mouse button is doing with the select tool
+#[derive(Debug, Clone, Default)]
+pub(super) enum SelectGesture {
+    #[default]
+    None,
+    Box(Vec2, SelectMode),
+    Lasso(Vec<Vec2>, SelectMode),
+    // Offsets of the dragged points from the mouse, so the selection moves rigidly.
+    // Moving only starts once the mouse moved away from the start
+    Drag {
+        offsets: Vec<(usize, Vec2)>,
+        start: Vec2,
+        moving: bool,
+    },
+}
+
+
+impl Simulation {
+    // Picking, box and lasso selection and dragging of the selection
+    pub(super) fn handle_select_gestures(&mut self) {
+        let mouse_pos = self.mouse_position();
+        if self.tool != Tool::Select {
+            self.select_gesture = SelectGesture::None;
+            return;
+        }
+
+        if self.input.is_key_pressed(KeyCode::Delete) && !self.selection.is_empty() {
+            self.delete_selection();
+        }
+
+        if self.input.is_pressed(MouseButton::Left) && !self.input.mouse_over_ui {
+            let mode = SelectMode::from_input(&self.input);
+            // Points take priority over the links they are attached to
+            let picked = if let Some(i) = self.nearest_point(mouse_pos, self.camera.pixels(POINT_RADIUS)) {
+                Some((vec![i], vec![]))
+            } else {
+                self.nearest_link(mouse_pos, self.camera.pixels(POINT_RADIUS*(SELECT_GRACE - 1.0))).map(|i| (vec![], vec![i]))
+            };
+            self.select_gesture = match picked {
+                Some((points, links)) => {
+                    // Clicking something that is already selected keeps the selection, so all of it can be dragged
+                    let is_selected = points.iter().all(|idx| self.selection.has_point(*idx)) && links.iter().all(|idx| self.selection.has_link(*idx));
+                    if mode != SelectMode::Replace || !is_selected {
+                        self.selection.combine(points, links, mode);
+                    }
+                    if mode == SelectMode::Replace {
+                        let offsets = self.selection.moved_points(&self.snapshot.links).into_iter()
+                            .map(|idx| (idx, self.snapshot.positions[idx] - mouse_pos))
+                            .collect();
+                        SelectGesture::Drag { offsets, start: mouse_pos, moving: false }
+                    } else {
+                        SelectGesture::None
+                    }
+                },
+                None => match self.select_shape {
+                    SelectShape::Box => SelectGesture::Box(mouse_pos, mode),
+                    SelectShape::Lasso => SelectGesture::Lasso(vec![mouse_pos], mode),
+                },
+            };
+        }
+
+        let released = !self.input.is_down(MouseButton::Left);
+        self.select_gesture = match std::mem::take(&mut self.select_gesture) {
+            SelectGesture::None => SelectGesture::None,
+            SelectGesture::Box(start, mode) => {
+                let min = start.min(mouse_pos);
+                let max = start.max(mouse_pos);
+                if released {
+                    let points = self.points_in_rect(min, max);
+                    let links = self.links_between(&points, self.links_in_rect(min, max));
+                    self.selection.combine(points, links, mode);
+                    SelectGesture::None
+                } else {
+                    SelectGesture::Box(start, mode)
+                }
+            },
+            SelectGesture::Lasso(mut outline, mode) => {
+                if outline.last().is_some_and(|last| last.distance(mouse_pos) > self.camera.pixels(LASSO_SPACING)) {
+                    outline.push(mouse_pos);
+                }
+                if released {
+                    let min = outline.iter().fold(Vec2::splat(f32::MAX), |min, pos| min.min(*pos));
+                    let max = outline.iter().fold(Vec2::splat(f32::MIN), |max, pos| max.max(*pos));
+                    let mut points = self.points_in_rect(min, max);
+                    points.retain(|idx| is_in_polygon(self.snapshot.positions[*idx], &outline));
+                    let links = self.links_between(&points, self.links_in_rect(min, max));
+                    self.selection.combine(points, links, mode);
+                    SelectGesture::None
+                } else {
+                    SelectGesture::Lasso(outline, mode)
+                }
+            },
+            SelectGesture::Drag { offsets, start, mut moving } => {
+                if released {
+                    SelectGesture::None
+                } else {
+                    moving |= mouse_pos != start;
+                    if moving {
+                        for (point_idx, offset) in offsets.iter() {
+                            self.push_command(Command::MovePoint(*point_idx, mouse_pos + *offset));
+                        }
+                    }
+                    SelectGesture::Drag { offsets, start, moving }
+                }
+            },
+        };
+    }
+
+
+    // Outline of the box or lasso that is being dragged
+    pub(super) fn draw_select_preview(&self, renderer: &mut impl Renderer, mouse_pos: Vec2) {
+        match &self.select_gesture {
+            SelectGesture::Box(start, _) => {
+                let min = start.min(mouse_pos);
+                renderer.rect_lines(min, start.max(mouse_pos) - min, self.camera.pixels(1.0), SELECT_COLOR);
+            },
+            SelectGesture::Lasso(outline, _) => renderer.polygon_lines(outline, self.camera.pixels(1.0), SELECT_COLOR),
+            _ => (),
+        }
+    }
+
+
+    // Removes the selected links, and the selected points together with their links
+    fn delete_selection(&mut self) {
+        let links = self.selection.links.iter().filter_map(|idx| self.link_ref(*idx)).collect::<Vec<_>>();
+        if !links.is_empty() {
+            self.push_command(Command::CutLinks(links));
+        }
+        let points = std::mem::take(&mut self.selection.points);
+        self.remove_points(&points);
+        self.selection.clear();
+        self.select_gesture = SelectGesture::None;
+    }
+
+
+    // The links that connect two of the (sorted) points
+    fn links_between(&self, points: &[usize], links: Vec<usize>) -> Vec<usize> {
+        links.into_iter().filter(|idx| {
+            let link = &self.snapshot.links[*idx];
+            points.binary_search(&link.from_idx).is_ok() && points.binary_search(&link.to_idx).is_ok()
+        }).collect()
+    }
+
+
+    // Edits all selected points and links at once. It shows the values of the first point and link,
+    // and a value that gets changed is set on all of them
+    pub(super) fn draw_group_inspector(&mut self) {
+        let color_picker_texture = self.color_picker_texture();
+        let points = self.selection.points.clone();
+        let links = self.selection.links.clone();
+        let first_point = points.first().copied();
+        let old_color = first_point.map_or(WHITE, |idx| self.snapshot.colors[idx]);
+        let old_mass = first_point.map_or(1.0, |idx| self.snapshot.masses[idx]);
+        let all_fixed = points.iter().all(|idx| self.snapshot.fixed[*idx]);
+        let old_link = links.first().map(|idx| self.snapshot.links[*idx].clone());
+        let mut color = old_color;
+        let mut mass = old_mass;
+        let mut fixed = all_fixed;
+        let mut new_link = old_link.clone();
+
+        ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), vec2(250.0, 300.0))
+            .label(&format!("Editing {} points, {} links", points.len(), links.len()))
+            .movable(false)
+            .ui(&mut ui::root_ui(), |ui| {
+                if first_point.is_some() {
+                    colorbox(
+                        ui,
+                        hash!(),
+                        "Color",
+                        &mut color,
+                        color_picker_texture.clone(),
+                    );
+                    ui.slider(hash!(), "Mass (kg)", 0.1f32..50f32, &mut mass);
+                    ui.checkbox(hash!(), "Fixed", &mut fixed);
+                }
+                if let Some(link) = new_link.as_mut() {
+                    ui.slider(hash!(), "Min length (m)", 0f32..10f32, &mut link.min_length);
+                    ui.slider(hash!(), "Max length (m)", 0f32..10f32, &mut link.max_length);
+                    ui.slider(hash!(), "Stiffness", 0f32..1f32, &mut link.stiffness);
+                    ui.slider(hash!(), "Damping", 0f32..1f32, &mut link.damping);
+                }
+        });
+
+        for point_idx in points {
+            if color != old_color {
+                self.push_command(Command::SetPointColor(point_idx, color));
+            }
+            if mass != old_mass {
+                self.push_command(Command::SetMass(point_idx, mass));
+            }
+            if fixed != all_fixed {
+                self.push_command(Command::SetFixed(point_idx, fixed));
+            }
+        }
+        let (Some(old_link), Some(new_link)) = (old_link, new_link) else {
+            return;
+        };
+        for link_idx in links {
+            let current = &self.snapshot.links[link_idx];
+            let mut params = current.clone();
+            if new_link.min_length != old_link.min_length {
+                params.min_length = new_link.min_length;
+            }
+            if new_link.max_length != old_link.max_length {
+                params.max_length = new_link.max_length;
+            }
+            if new_link.stiffness != old_link.stiffness {
+                params.stiffness = new_link.stiffness;
+            }
+            if new_link.damping != old_link.damping {
+                params.damping = new_link.damping;
+            }
+            if (params.min_length, params.max_length, params.stiffness, params.damping) != (current.min_length, current.max_length, current.stiffness, current.damping) {
+                self.push_command(Command::SetLinkParams(LinkRef::new(link_idx, current), params));
+            }
+        }
+    }
+}
+
+
+// Even-odd rule, thanks to https://wrfranklin.org/Research/Short_Notes/pnpoly.html
+fn is_in_polygon(point: Vec2, polygon: &[Vec2]) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > point.y) != (b.y > point.y) && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
@@ -0,0 +1,203 @@
+use macroquad::{color::Color, math::Vec2};
+
+use super::{energy::EnergySample, island::{BodySplit, Component, Islands}, profiler::StepTimings, remove_indices, replay::ReplayEvent, IKChain, InstabilityEvent, Link, LinkBreak, SimulationState};
+
+
+// Changes to the points and links, so copies of the link list can be kept up to date without copying all of them.
+// The point data itself is copied every frame
+#[derive(Debug, Clone)]
+pub(super) enum TopologyEvent {
+    // Number of points that were pushed to the end of the list
+    PointsAdded(usize),
+    // Sorted indices of the points that got removed at once, after their links were removed
+    PointsRemoved(Vec<usize>),
+    // A link that was pushed to the end of the list
+    Added(Link),
+    // Sorted indices of the links that got removed at once
+    Removed(Vec<usize>),
+    // The parameters of the link at that index were changed
+    Changed(usize, Link),
+    // All links and islands were replaced, because the state was rolled back to a checkpoint
+    Reset(Vec<Link>, Islands),
+}
+
+
+// The data of one published frame, sent from the simulation thread to the render thread.
+// The render thread sends it back once it has been applied, so the buffers can be reused
+#[derive(Debug, Default)]
+pub(super) struct FrameUpdate {
+    positions: Vec<Vec2>,
+    prev_positions: Vec<Vec2>,
+    colors: Vec<Color>,
+    fixed: Vec<bool>,
+    masses: Vec<f32>,
+    ik_chains: Vec<IKChain>,
+    timings: StepTimings,
+    energy_samples: Vec<EnergySample>,
+    link_breaks: Vec<LinkBreak>,
+    step_delta: f32,
+    instabilities: Vec<InstabilityEvent>,
+    replay_events: Vec<ReplayEvent>,
+    topology_events: Vec<TopologyEvent>,
+}
+impl FrameUpdate {
+    pub(super) fn fill(&mut self, state: &mut SimulationState) {
+        self.positions.clone_from(&state.positions);
+        self.prev_positions.clone_from(&state.prev_positions);
+        self.colors.clone_from(&state.colors);
+        self.fixed.clone_from(&state.fixed);
+        self.masses.clone_from(&state.masses);
+        self.ik_chains.clone_from(&state.ik_chains);
+        self.timings = std::mem::take(&mut state.timings);
+        self.energy_samples.clear();
+        self.energy_samples.append(&mut state.energy_samples);
+        self.link_breaks.clear();
+        self.link_breaks.append(&mut state.link_breaks);
+        self.step_delta = state.step_delta;
+        self.instabilities.clear();
+        self.instabilities.append(&mut state.guard.events);
+        self.replay_events.clear();
+        self.replay_events.append(&mut state.replay_events);
+        self.topology_events.clear();
+        self.topology_events.append(&mut state.topology_events);
+    }
+}
+
+
+// Everything that is needed to draw and edit a frame. Only the point data gets copied each frame,
+// the links are kept up to date through the link events of the state
+#[derive(Debug, Default)]
+pub(super) struct RenderSnapshot {
+    pub(super) positions: Vec<Vec2>,
+    // Positions one step earlier, for the velocities
+    pub(super) prev_positions: Vec<Vec2>,
+    pub(super) colors: Vec<Color>,
+    pub(super) fixed: Vec<bool>,
+    pub(super) masses: Vec<f32>,
+    pub(super) links: Vec<Link>,
+    // Copied every frame, there are only a few of them
+    pub(super) ik_chains: Vec<IKChain>,
+    // Time spent in the steps since they were last taken
+    pub(super) step_timings: StepTimings,
+    // Energy after every step since it was last drained
+    pub(super) energy_samples: Vec<EnergySample>,
+    // Time of the substeps that produced the positions, for the velocities
+    pub(super) step_delta: f32,
+    // Links that broke since they were last cleared
+    pub(super) link_breaks: Vec<LinkBreak>,
+    // Found by the guard since they were last cleared
+    pub(super) instabilities: Vec<InstabilityEvent>,
+    // Recordings and playback results since they were last drained
+    pub(super) replay_events: Vec<ReplayEvent>,
+    // Connected components, kept up to date with the link events
+    pub(super) islands: Islands,
+    // Splits that happened since they were last cleared
+    pub(super) body_splits: Vec<BodySplit>,
+    // The link removals that got applied since the removals were last cleared, one list per removal
+    removed_links: Vec<Vec<usize>>,
+    // Same for the point removals
+    removed_points: Vec<Vec<usize>>,
+    // Set when the links were reset since it was last taken
+    reset: bool,
+}
+impl RenderSnapshot {
+    pub(super) fn update(&mut self, state: &mut SimulationState) {
+        // clone_from reuses the allocations of the previous snapshot
+        self.positions.clone_from(&state.positions);
+        self.prev_positions.clone_from(&state.prev_positions);
+        self.colors.clone_from(&state.colors);
+        self.fixed.clone_from(&state.fixed);
+        self.masses.clone_from(&state.masses);
+        self.ik_chains.clone_from(&state.ik_chains);
+        self.step_timings.add(&std::mem::take(&mut state.timings));
+        self.energy_samples.append(&mut state.energy_samples);
+        self.link_breaks.append(&mut state.link_breaks);
+        self.step_delta = state.step_delta;
+        self.instabilities.append(&mut state.guard.events);
+        self.replay_events.append(&mut state.replay_events);
+        let events = std::mem::take(&mut state.topology_events);
+        self.apply_topology_events(events);
+    }
+
+
+    // Takes over the buffers of the frame, and leaves the old buffers in it so they can be reused
+    pub(super) fn apply_frame(&mut self, frame: &mut FrameUpdate) {
+        std::mem::swap(&mut self.positions, &mut frame.positions);
+        std::mem::swap(&mut self.prev_positions, &mut frame.prev_positions);
+        std::mem::swap(&mut self.colors, &mut frame.colors);
+        std::mem::swap(&mut self.fixed, &mut frame.fixed);
+        std::mem::swap(&mut self.masses, &mut frame.masses);
+        std::mem::swap(&mut self.ik_chains, &mut frame.ik_chains);
+        self.step_timings.add(&frame.timings);
+        self.energy_samples.append(&mut frame.energy_samples);
+        self.link_breaks.append(&mut frame.link_breaks);
+        self.step_delta = frame.step_delta;
+        self.instabilities.append(&mut frame.instabilities);
+        self.replay_events.append(&mut frame.replay_events);
+        let events = std::mem::take(&mut frame.topology_events);
+        self.apply_topology_events(events);
+    }
+
+
+    fn apply_topology_events(&mut self, events: Vec<TopologyEvent>) {
+        for event in events {
+            match event {
+                TopologyEvent::PointsAdded(count) => self.islands.add_points(count),
+                TopologyEvent::PointsRemoved(removed) => {
+                    let shift = |point_idx: usize| point_idx - removed.partition_point(|idx| *idx < point_idx);
+                    for link in self.links.iter_mut() {
+                        link.from_idx = shift(link.from_idx);
+                        link.to_idx = shift(link.to_idx);
+                    }
+                    self.islands.remove_points(&removed);
+                    self.removed_points.push(removed);
+                },
+                TopologyEvent::Added(link) => {
+                    self.islands.add_link(link.from_idx, link.to_idx);
+                    self.links.push(link);
+                },
+                TopologyEvent::Removed(removed) => {
+                    for link_idx in removed.iter() {
+                        self.islands.remove_link(self.links[*link_idx].from_idx, self.links[*link_idx].to_idx);
+                    }
+                    self.body_splits.extend(self.islands.resolve_splits());
+                    remove_indices(&mut self.links, &removed);
+                    self.removed_links.push(removed);
+                },
+                TopologyEvent::Changed(link_idx, link) => {
+                    if let Some(snapshot_link) = self.links.get_mut(link_idx) {
+                        *snapshot_link = link;
+                    }
+                },
+                TopologyEvent::Reset(links, islands) => {
+                    self.links = links;
+                    self.islands = islands;
+                    self.reset = true;
+                },
+            }
+        }
+    }
+
+
+    pub(super) fn components(&self) -> Vec<Component> {
+        self.islands.islands.iter().map(|island| self.islands.component(island, &self.positions, &self.masses)).collect()
+    }
+
+
+    // Link removals since the last call, in the order they happened
+    pub(super) fn take_removed_links(&mut self) -> Vec<Vec<usize>> {
+        std::mem::take(&mut self.removed_links)
+    }
+
+
+    // Point removals since the last call, in the order they happened
+    pub(super) fn take_removed_points(&mut self) -> Vec<Vec<usize>> {
+        std::mem::take(&mut self.removed_points)
+    }
+
+
+    // Whether the links were reset since the last call
+    pub(super) fn take_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset)
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+use macroquad::{color::Color, math::{Mat2, Vec2}};
+
+use super::{render::{RenderTexture, Renderer, Space}, Camera};
+
+
+// Rough width of a character relative to the font size, SVG viewers pick the font themselves
+const CHAR_WIDTH: f32 = 0.55;
+
+
+/// Writes everything drawn with it into an SVG file, in screen pixels of the camera
+#[derive(Debug)]
+pub struct SvgRenderer {
+    camera: Camera,
+    space: Space,
+    defs: String,
+    body: String,
+    // Id of every texture that is in the defs already, by address
+    textures: HashMap<*const RenderTexture, usize>,
+    clip_paths: usize,
+}
+impl SvgRenderer {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            camera: camera.clone(),
+            space: Space::World,
+            defs: String::new(),
+            body: String::new(),
+            textures: HashMap::new(),
+            clip_paths: 0,
+        }
+    }
+
+
+    /// The whole file, with the background behind everything that was drawn
+    pub fn finish(&self, background: Color) -> String {
+        let size = self.camera.screen_size;
+        let mut svg = String::new();
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#, w = size.x, h = size.y).unwrap();
+        if !self.defs.is_empty() {
+            writeln!(svg, "<defs>\n{}</defs>", self.defs).unwrap();
+        }
+        writeln!(svg, r#"<rect width="100%" height="100%" {}/>"#, fill(background)).unwrap();
+        svg.push_str(&self.body);
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+
+    pub fn save(&self, path: impl AsRef<Path>, background: Color) -> std::io::Result<()> {
+        std::fs::write(path, self.finish(background))
+    }
+
+
+    fn pos(&self, pos: Vec2) -> Vec2 {
+        match self.space {
+            Space::World => self.camera.world_to_screen(pos),
+            Space::Screen => pos,
+        }
+    }
+
+
+    fn length(&self, length: f32) -> f32 {
+        match self.space {
+            Space::World => length * self.camera.scale(),
+            Space::Screen => length,
+        }
+    }
+
+
+    fn points(&self, points: &[Vec2]) -> String {
+        points.iter().map(|pos| {
+            let pos = self.pos(*pos);
+            format!("{:.2},{:.2}", pos.x, pos.y)
+        }).collect::<Vec<_>>().join(" ")
+    }
+
+
+    // Id of the texture, adds it to the defs the first time
+    fn texture_id(&mut self, texture: &RenderTexture) -> usize {
+        let next_id = self.textures.len();
+        let id = *self.textures.entry(texture as *const _).or_insert(next_id);
+        if id == next_id {
+            let image = texture.image();
+            let mut png = vec![];
+            PngEncoder::new(&mut png).write_image(&image.bytes, image.width as u32, image.height as u32, ColorType::Rgba8).unwrap();
+            writeln!(self.defs, r#"<image id="texture{}" width="{}" height="{}" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+                id, image.width, image.height, base64(&png)).unwrap();
+        }
+        id
+    }
+}
+impl Renderer for SvgRenderer {
+    fn set_space(&mut self, space: Space) {
+        self.space = space;
+    }
+
+
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: f32, color: Color) {
+        let (from, to) = (self.pos(from), self.pos(to));
+        writeln!(self.body, r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke-width="{:.2}" {}/>"#,
+            from.x, from.y, to.x, to.y, self.length(thickness), stroke(color)).unwrap();
+    }
+
+
+    fn circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let center = self.pos(center);
+        writeln!(self.body, r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" {}/>"#, center.x, center.y, self.length(radius), fill(color)).unwrap();
+    }
+
+
+    fn circle_lines(&mut self, center: Vec2, radius: f32, thickness: f32, color: Color) {
+        let center = self.pos(center);
+        writeln!(self.body, r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="none" stroke-width="{:.2}" {}/>"#,
+            center.x, center.y, self.length(radius), self.length(thickness), stroke(color)).unwrap();
+    }
+
+
+    fn polygon(&mut self, points: &[Vec2], color: Color) {
+        writeln!(self.body, r#"<polygon points="{}" {}/>"#, self.points(points), fill(color)).unwrap();
+    }
+
+
+    fn polygon_lines(&mut self, points: &[Vec2], thickness: f32, color: Color) {
+        writeln!(self.body, r#"<polygon points="{}" fill="none" stroke-width="{:.2}" {}/>"#,
+            self.points(points), self.length(thickness), stroke(color)).unwrap();
+    }
+
+
+    fn text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) {
+        let pos = self.pos(pos);
+        let text = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        writeln!(self.body, r#"<text x="{:.2}" y="{:.2}" font-family="sans-serif" font-size="{:.2}" {}>{}</text>"#,
+            pos.x, pos.y, self.length(size), fill(color), text).unwrap();
+    }
+
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().count() as f32 * size * CHAR_WIDTH
+    }
+
+
+    // The image gets clipped to the triangle, and moved so the uvs land on the corners.
+    // The color only changes the opacity, SVG cant tint images
+    fn textured_triangle(&mut self, points: [Vec2; 3], uvs: [Vec2; 3], texture: &RenderTexture, color: Color) {
+        let image_size = Vec2::new(texture.image().width as f32, texture.image().height as f32);
+        let uvs = uvs.map(|uv| uv * image_size);
+        let corners = points.map(|pos| self.pos(pos));
+        let uv_axes = Mat2::from_cols(uvs[1] - uvs[0], uvs[2] - uvs[0]);
+        if uv_axes.determinant().abs() < f32::EPSILON {
+            return;
+        }
+        let matrix = Mat2::from_cols(corners[1] - corners[0], corners[2] - corners[0]) * uv_axes.inverse();
+        let offset = corners[0] - matrix * uvs[0];
+        let id = self.texture_id(texture);
+        let clip = self.clip_paths;
+        self.clip_paths += 1;
+        let clip_points = corners.iter().map(|pos| format!("{:.2},{:.2}", pos.x, pos.y)).collect::<Vec<_>>().join(" ");
+        writeln!(self.defs, r#"<clipPath id="clip{}"><polygon points="{}"/></clipPath>"#, clip, clip_points).unwrap();
+        writeln!(self.body, r##"<g clip-path="url(#clip{})" opacity="{:.3}"><use href="#texture{}" transform="matrix({} {} {} {} {} {})"/></g>"##,
+            clip, color.a, id, matrix.x_axis.x, matrix.x_axis.y, matrix.y_axis.x, matrix.y_axis.y, offset.x, offset.y).unwrap();
+    }
+}
+
+
+fn rgb(color: Color) -> String {
+    let [r, g, b, _]: [u8; 4] = color.into();
+    format!("rgb({},{},{})", r, g, b)
+}
+
+
+fn fill(color: Color) -> String {
+    format!(r#"fill="{}" fill-opacity="{:.3}""#, rgb(color), color.a)
+}
+
+
+fn stroke(color: Color) -> String {
+    format!(r#"stroke="{}" stroke-opacity="{:.3}""#, rgb(color), color.a)
+}
+
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,121 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Receiver, Sender, TryRecvError}, Arc}, thread::JoinHandle, time::{Duration, Instant}};
+
+use super::{command::Command, snapshot::{FrameUpdate, RenderSnapshot}, Simulation, SimulationState};
+
+
+// Frames that havent been picked up by the render thread yet. Above this, the simulation thread
+// skips publishing instead of piling up frames
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+// When the simulation thread falls behind by more than this, it gives up on catching up
+const MAX_STEP_LAG: Duration = Duration::from_millis(250);
+
+
+// Steps the state on its own thread at a fixed rate. Edits are sent to it as commands,
+// and after every `Simulation::UPDATE_STEPS` steps it publishes a frame for drawing.
+// Everything goes through channels, so neither side ever waits for the other.
+#[derive(Debug)]
+pub(super) struct SimulationThread {
+    commands: Option<Sender<Command>>,
+    frames: Receiver<FrameUpdate>,
+    recycled_frames: Sender<FrameUpdate>,
+    frames_in_flight: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+impl SimulationThread {
+    pub(super) fn spawn(mut state: SimulationState, delta: f32, steps_per_second: f32) -> Self {
+        let (command_sender, commands) = mpsc::channel();
+        let (frame_sender, frames) = mpsc::channel();
+        let (recycled_frames, recycled_frame_receiver) = mpsc::channel::<FrameUpdate>();
+        let frames_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let thread_frames_in_flight = frames_in_flight.clone();
+        let handle = std::thread::Builder::new().name("simulation".to_owned()).spawn(move || {
+            let step_time = Duration::from_secs_f32(1.0 / steps_per_second);
+            let mut next_step = Instant::now();
+            let mut paused = false;
+            let mut steps_since_frame = 0;
+            let publish = |state: &mut SimulationState| {
+                if thread_frames_in_flight.load(Ordering::Acquire) >= MAX_FRAMES_IN_FLIGHT {
+                    return;
+                }
+                let mut frame = recycled_frame_receiver.try_recv().unwrap_or_default();
+                frame.fill(state);
+                thread_frames_in_flight.fetch_add(1, Ordering::AcqRel);
+                let _ = frame_sender.send(frame);
+            };
+
+            loop {
+                let mut received_commands = false;
+                loop {
+                    match commands.try_recv() {
+                        Ok(Command::SetPaused(val)) => paused = val,
+                        Ok(command) => state.apply_command(command),
+                        Err(TryRecvError::Empty) => break,
+                        // The simulation got dropped
+                        Err(TryRecvError::Disconnected) => return,
+                    }
+                    received_commands = true;
+                }
+
+                if paused {
+                    // Still publish, so edits show up while paused
+                    if received_commands {
+                        publish(&mut state);
+                    }
+                    std::thread::sleep(step_time);
+                    next_step = Instant::now();
+                    continue;
+                }
+
+                Simulation::update_state(&mut state, delta);
+                steps_since_frame += 1;
+                if steps_since_frame >= Simulation::UPDATE_STEPS {
+                    publish(&mut state);
+                    steps_since_frame = 0;
+                }
+
+                next_step += step_time;
+                let now = Instant::now();
+                if next_step > now {
+                    std::thread::sleep(next_step - now);
+                } else if now - next_step > MAX_STEP_LAG {
+                    next_step = now;
+                }
+            }
+        }).expect("Failed to spawn the simulation thread");
+
+        Self {
+            commands: Some(command_sender),
+            frames,
+            recycled_frames,
+            frames_in_flight,
+            handle: Some(handle),
+        }
+    }
+
+
+    pub(super) fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+
+    // Applies every frame that has been published since the last call
+    pub(super) fn receive(&self, snapshot: &mut RenderSnapshot) {
+        while let Ok(mut frame) = self.frames.try_recv() {
+            self.frames_in_flight.fetch_sub(1, Ordering::AcqRel);
+            snapshot.apply_frame(&mut frame);
+            let _ = self.recycled_frames.send(frame);
+        }
+    }
+}
+impl Drop for SimulationThread {
+    fn drop(&mut self) {
+        // Closing the command channel stops the thread
+        self.commands = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::fmt;
+
+use super::{
+    attached_links, links_to_cut, points_to_remove, remove_indices, shift_link_points, Command, IKChain, KinematicPath, Link, PathShape,
+    Point, Scene, Simulation,
+};
+
+
+/// Why a point, link or IK chain got rejected by one of the `try_` functions of the simulation
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// A link refers to a point that doesnt exist (yet)
+    PointIndex { idx: usize, num_points: usize },
+    /// An IK chain refers to a link that doesnt exist (yet)
+    LinkIndex { idx: usize, num_links: usize },
+    /// A link from the point to itself
+    SelfLink(usize),
+    /// The named value is NaN or infinite
+    NonFinite(&'static str),
+    /// The named value is below 0
+    Negative(&'static str, f32),
+    /// Masses have to be above 0, the links divide by them
+    Mass(f32),
+    /// The min length of a link is larger than its max length
+    LengthRange { min: f32, max: f32 },
+    EmptyChain,
+    /// The link at this position of the IK chain doesnt start at the point the link before it ends at
+    ChainGap(usize),
+    /// Kinematic paths have to take longer than 0 seconds
+    Duration(f32),
+    /// Kinematic path without keyframes
+    EmptyPath,
+}
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::PointIndex { idx, num_points } => write!(f, "point {} doesnt exist, there are {} points", idx, num_points),
+            BuildError::LinkIndex { idx, num_links } => write!(f, "link {} doesnt exist, there are {} links", idx, num_links),
+            BuildError::SelfLink(idx) => write!(f, "link from point {} to itself", idx),
+            BuildError::NonFinite(name) => write!(f, "{} is not a finite number", name),
+            BuildError::Negative(name, value) => write!(f, "{} can't be negative, found {}", name, value),
+            BuildError::Mass(mass) => write!(f, "mass has to be above 0, found {}", mass),
+            BuildError::LengthRange { min, max } => write!(f, "min length {} is larger than max length {}", min, max),
+            BuildError::EmptyChain => write!(f, "IK chain without links"),
+            BuildError::ChainGap(position) => write!(f, "link {} of the IK chain doesnt start where the link before it ends", position),
+            BuildError::Duration(duration) => write!(f, "path has to take longer than 0 s, found {}", duration),
+            BuildError::EmptyPath => write!(f, "path without keyframes"),
+        }
+    }
+}
+impl std::error::Error for BuildError {}
+
+
+// Errors if the value is NaN or infinite, or below 0
+fn check_non_negative(name: &'static str, value: f32) -> Result<(), BuildError> {
+    if !value.is_finite() {
+        return Err(BuildError::NonFinite(name));
+    }
+    if value < 0.0 {
+        return Err(BuildError::Negative(name, value));
+    }
+    Ok(())
+}
+
+
+impl Point {
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if !self.position.is_finite() {
+            return Err(BuildError::NonFinite("position"));
+        }
+        if !self.mass.is_finite() {
+            return Err(BuildError::NonFinite("mass"));
+        }
+        if self.mass <= 0.0 {
+            return Err(BuildError::Mass(self.mass));
+        }
+        match &self.path {
+            Some(path) => path.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+
+impl KinematicPath {
+    /// Checks that sampling the path gives finite positions
+    pub fn validate(&self) -> Result<(), BuildError> {
+        let finite = match &self.shape {
+            PathShape::Line { from, to } => from.is_finite() && to.is_finite(),
+            PathShape::Circle { center, radius, start_angle } => center.is_finite() && radius.is_finite() && start_angle.is_finite(),
+            PathShape::Bezier { p0, p1, p2, p3 } => [p0, p1, p2, p3].iter().all(|p| p.is_finite()),
+            PathShape::Keyframes(frames) => {
+                if frames.is_empty() {
+                    return Err(BuildError::EmptyPath);
+                }
+                frames.iter().all(|(time, pos)| time.is_finite() && pos.is_finite())
+            },
+        };
+        if !finite {
+            return Err(BuildError::NonFinite("path"));
+        }
+        if !self.time_offset.is_finite() {
+            return Err(BuildError::NonFinite("path time offset"));
+        }
+        if !self.duration.is_finite() {
+            return Err(BuildError::NonFinite("path duration"));
+        }
+        if self.duration <= 0.0 {
+            return Err(BuildError::Duration(self.duration));
+        }
+        Ok(())
+    }
+}
+
+
+impl Link {
+    /// Checks the link on its own, and that it connects two different ones of the first `num_points` points
+    pub fn validate(&self, num_points: usize) -> Result<(), BuildError> {
+        for idx in [self.from_idx, self.to_idx] {
+            if idx >= num_points {
+                return Err(BuildError::PointIndex { idx, num_points });
+            }
+        }
+        if self.from_idx == self.to_idx {
+            return Err(BuildError::SelfLink(self.from_idx));
+        }
+        check_non_negative("min length", self.min_length)?;
+        check_non_negative("max length", self.max_length)?;
+        if self.min_length > self.max_length {
+            return Err(BuildError::LengthRange { min: self.min_length, max: self.max_length });
+        }
+        check_non_negative("stiffness", self.stiffness)?;
+        check_non_negative("damping", self.damping)?;
+        // Links that never break have an infinite max stress
+        if self.max_stress.is_nan() {
+            return Err(BuildError::NonFinite("max stress"));
+        }
+        if self.max_stress < 0.0 {
+            return Err(BuildError::Negative("max stress", self.max_stress));
+        }
+        Ok(())
+    }
+}
+
+
+impl IKChain {
+    /// Checks that the chain refers to existing links, given as (from, to) point indices,
+    /// and that every link starts where the one before it ends
+    pub fn validate(&self, links: &[(usize, usize)]) -> Result<(), BuildError> {
+        if self.links.is_empty() {
+            return Err(BuildError::EmptyChain);
+        }
+        let mut prev_end = None;
+        for (position, link_idx) in self.links.iter().enumerate() {
+            let Some((from_idx, to_idx)) = links.get(*link_idx) else {
+                return Err(BuildError::LinkIndex { idx: *link_idx, num_links: links.len() });
+            };
+            if prev_end.is_some_and(|end| end != *from_idx) {
+                return Err(BuildError::ChainGap(position));
+            }
+            prev_end = Some(*to_idx);
+        }
+        if !self.target_position.is_finite() {
+            return Err(BuildError::NonFinite("target position"));
+        }
+        check_non_negative("error margin", self.error_margin)?;
+        check_non_negative("max angle per link", self.max_angle_per_link)
+    }
+}
+
+
+impl Scene {
+    pub fn validate(&self) -> Result<(), BuildError> {
+        for point in self.points.iter() {
+            point.validate()?;
+        }
+        for link in self.links.iter() {
+            link.validate(self.points.len())?;
+        }
+        if let Some(bounds) = self.bounds {
+            check_non_negative("bounds width", bounds.x)?;
+            check_non_negative("bounds height", bounds.y)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Like the functions without `try_`, but they check everything first and add nothing if anything is wrong
+impl Simulation {
+    /// Adds the point and returns its index
+    pub fn try_add_point(&mut self, point: Point) -> Result<usize, BuildError> {
+        point.validate()?;
+        self.add_point(point);
+        Ok(self.num_points - 1)
+    }
+
+
+    pub fn try_add_points(&mut self, points: &[Point]) -> Result<(), BuildError> {
+        for point in points.iter() {
+            point.validate()?;
+        }
+        self.add_points(points);
+        Ok(())
+    }
+
+
+    /// The points may also be ones that were just added and havent been simulated yet
+    pub fn try_add_link(&mut self, link: Link) -> Result<(), BuildError> {
+        link.validate(self.num_points)?;
+        self.add_link(link);
+        Ok(())
+    }
+
+
+    /// The links are the ones of the last drawn frame, with the links that were added, cut or removed since.
+    /// With the simulation on its own thread, the last drawn frame might not have all earlier links yet
+    pub fn try_add_ik_chain(&mut self, ik_chain: IKChain) -> Result<(), BuildError> {
+        ik_chain.validate(&self.expected_links())?;
+        self.add_ik_chain(ik_chain);
+        Ok(())
+    }
+
+
+    // The links of the last drawn frame after the queued edits, applied the same way apply_command does
+    fn expected_links(&self) -> Vec<(usize, usize)> {
+        let mut num_points = self.snapshot.positions.len();
+        let mut links = self.snapshot.links.clone();
+        for edit in self.link_edits.iter() {
+            match edit {
+                Command::AddPoints(points) => num_points += points.len(),
+                Command::RemovePoints(removed) => {
+                    let removed = points_to_remove(removed.clone(), num_points);
+                    let attached = attached_links(&links, &removed);
+                    remove_indices(&mut links, &attached);
+                    shift_link_points(&mut links, &removed);
+                    num_points -= removed.len();
+                },
+                Command::AddLink(link) if link.validate(num_points).is_ok() => links.push(link.clone()),
+                Command::CutLinks(link_refs) => {
+                    let cut = links_to_cut(&links, link_refs);
+                    remove_indices(&mut links, &cut);
+                },
+                _ => (),
+            }
+        }
+        links.iter().map(|link| (link.from_idx, link.to_idx)).collect()
+    }
+
+
+    pub fn try_load_scene(&mut self, scene: &Scene) -> Result<(), BuildError> {
+        scene.validate()?;
+        self.load_scene(scene);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::BuildError;
+    use crate::simulation::{IKChain, KinematicPath, Link, PathShape, Point, Scene, Simulation};
+
+
+    const DELTA: f32 = 1.0 / 240.0;
+
+
+    fn point(x: f32) -> Point {
+        Point::new(Vec2::new(x, 1.0))
+    }
+
+
+    #[test]
+    fn links_need_two_existing_points() {
+        assert_eq!(Link::new(0, 1).validate(2), Ok(()));
+        assert_eq!(Link::new(0, 2).validate(2), Err(BuildError::PointIndex { idx: 2, num_points: 2 }));
+        assert_eq!(Link::new(1, 1).validate(2), Err(BuildError::SelfLink(1)));
+    }
+
+
+    #[test]
+    fn link_lengths_have_to_be_in_order() {
+        let link = Link::new(0, 1).min_length(2.0).max_length(1.0);
+        assert_eq!(link.validate(2), Err(BuildError::LengthRange { min: 2.0, max: 1.0 }));
+        assert_eq!(Link::new(0, 1).max_length(f32::INFINITY).validate(2), Err(BuildError::NonFinite("max length")));
+        assert_eq!(Link::new(0, 1).stiffness(-1.0).validate(2), Err(BuildError::Negative("stiffness", -1.0)));
+        // Links that never break are fine
+        assert_eq!(Link::new(0, 1).max_stress(f32::INFINITY).validate(2), Ok(()));
+    }
+
+
+    #[test]
+    fn points_need_mass() {
+        assert_eq!(point(0.0).mass(0.0).validate(), Err(BuildError::Mass(0.0)));
+        assert_eq!(point(f32::NAN).validate(), Err(BuildError::NonFinite("position")));
+    }
+
+
+    #[test]
+    fn paths_have_to_give_finite_positions() {
+        let line = |duration| KinematicPath::line(Vec2::ZERO, Vec2::ONE, duration);
+        assert_eq!(point(0.0).kinematic(line(1.0)).validate(), Ok(()));
+        assert_eq!(point(0.0).kinematic(line(0.0)).validate(), Err(BuildError::Duration(0.0)));
+        assert_eq!(point(0.0).kinematic(line(f32::INFINITY)).validate(), Err(BuildError::NonFinite("path duration")));
+        assert_eq!(point(0.0).kinematic(KinematicPath::keyframes(vec![])).validate(), Err(BuildError::EmptyPath));
+        let circle = KinematicPath::new(PathShape::Circle { center: Vec2::ZERO, radius: f32::NAN, start_angle: 0.0 }, 1.0);
+        assert_eq!(point(0.0).kinematic(circle).validate(), Err(BuildError::NonFinite("path")));
+    }
+
+
+    #[test]
+    fn ik_chains_have_to_be_connected() {
+        let links = [(0, 1), (1, 2), (3, 4)];
+        assert_eq!(IKChain::new(vec![0, 1]).validate(&links), Ok(()));
+        assert_eq!(IKChain::new(vec![]).validate(&links), Err(BuildError::EmptyChain));
+        assert_eq!(IKChain::new(vec![0, 3]).validate(&links), Err(BuildError::LinkIndex { idx: 3, num_links: 3 }));
+        assert_eq!(IKChain::new(vec![1, 2]).validate(&links), Err(BuildError::ChainGap(1)));
+    }
+
+
+    #[test]
+    fn try_functions_add_nothing_invalid() {
+        let mut simulation = Simulation::new();
+        assert_eq!(simulation.try_add_point(point(1.0)), Ok(0));
+        assert_eq!(simulation.try_add_point(point(2.0).mass(-1.0)), Err(BuildError::Mass(-1.0)));
+        // The point was just added and hasnt been simulated yet
+        assert_eq!(simulation.try_add_point(point(2.0)), Ok(1));
+        assert_eq!(simulation.try_add_link(Link::new(0, 1)), Ok(()));
+        assert_eq!(simulation.try_add_link(Link::new(1, 2)), Err(BuildError::PointIndex { idx: 2, num_points: 2 }));
+        assert_eq!(simulation.try_add_ik_chain(IKChain::new(vec![0])), Ok(()));
+        assert_eq!(simulation.try_add_ik_chain(IKChain::new(vec![1])), Err(BuildError::LinkIndex { idx: 1, num_links: 1 }));
+        simulation.step_headless(DELTA);
+        assert_eq!(simulation.positions().len(), 2);
+        assert_eq!(simulation.link_strains().count(), 1);
+
+        // The queued removal takes the link along, so the chain has nothing to refer to
+        simulation.remove_points(&[1]);
+        assert_eq!(simulation.try_add_ik_chain(IKChain::new(vec![0])), Err(BuildError::LinkIndex { idx: 0, num_links: 0 }));
+    }
+
+
+    #[test]
+    fn valid_scenes_load_and_invalid_ones_dont() {
+        let mut scene = Scene {
+            points: vec![point(1.0), point(2.0), point(3.0)],
+            links: vec![Link::new(0, 1), Link::new(1, 2)],
+            bounds: Some(Vec2::new(10.0, 5.0)),
+            pixels_per_meter: None,
+        };
+        let mut simulation = Simulation::new();
+        assert_eq!(simulation.try_load_scene(&scene), Ok(()));
+        simulation.step_headless(DELTA);
+        assert_eq!(simulation.positions().len(), 3);
+        assert_eq!(simulation.link_strains().count(), 2);
+        assert_eq!(simulation.bounds(), Vec2::new(10.0, 5.0));
+
+        scene.links.push(Link::new(2, 3));
+        let mut simulation = Simulation::new();
+        assert_eq!(simulation.try_load_scene(&scene), Err(BuildError::PointIndex { idx: 3, num_points: 3 }));
+        simulation.step_headless(DELTA);
+        assert!(simulation.positions().is_empty());
+    }
+}
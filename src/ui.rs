@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 use macroquad::{prelude::*, ui::{hash, Id, Ui}};
 
 
@@ -118,4 +120,56 @@ pub fn colorbox(ui: &mut Ui, id: Id, label: &str, data: &mut Color, color_picker
             }
         });
     }
-}
\ No newline at end of file
+}
+
+// Text field for a number that only accepts values inside of the range. The text follows the value
+// until it gets edited, then Enter applies it (if it is valid) and Escape throws it away
+#[derive(Debug, Default)]
+pub struct NumberField {
+    text: String,
+    editing: bool,
+}
+impl NumberField {
+    pub fn ui(&mut self, ui: &mut Ui, id: Id, label: &str, value: f32, range: RangeInclusive<f32>) -> Option<f32> {
+        if !self.editing {
+            self.text = format_number(value);
+        }
+        let text_before = self.text.clone();
+        ui.input_text(id, label, &mut self.text);
+        self.editing |= self.text != text_before;
+        if !self.editing {
+            return None;
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.editing = false;
+            return None;
+        }
+
+        match self.text.trim().parse::<f32>() {
+            Ok(val) if range.contains(&val) => {
+                if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+                    self.editing = false;
+                    return Some(val);
+                }
+                ui.label(None, "  Enter: apply, Esc: cancel");
+            },
+            Ok(_) => ui.label(None, &format!("  Has to be in {}..{}", format_number(*range.start()), format_number(*range.end()))),
+            Err(_) => ui.label(None, "  Not a number"),
+        }
+        None
+    }
+
+    // Stops editing, so the text shows the value again
+    pub fn reset(&mut self) {
+        self.editing = false;
+    }
+}
+
+
+fn format_number(value: f32) -> String {
+    if value.abs() >= 1e6 {
+        return format!("{:e}", value);
+    }
+    let text = format!("{:.3}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
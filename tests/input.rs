@@ -0,0 +1,41 @@
+use macroquad::math::Vec2;
+
+use verlet::simulation::{InputState, Link, Point, Simulation};
+
+
+const DELTA: f32 = 1.0 / 240.0;
+
+
+// A fixed row of three points, linked 0-1 and 1-2
+fn row() -> Simulation {
+    let mut simulation = Simulation::new();
+    simulation.set_deterministic(Some(DELTA));
+    simulation.add_points(&[
+        Point::new(Vec2::new(8.0, 5.0)).fixed(),
+        Point::new(Vec2::new(9.0, 5.0)).fixed(),
+        Point::new(Vec2::new(10.0, 5.0)).fixed(),
+    ]);
+    simulation.add_link(Link::new(0, 1));
+    simulation.add_link(Link::new(1, 2));
+    simulation
+}
+
+
+#[test]
+fn swipe_cuts_the_crossed_link() {
+    let mut simulation = row();
+    let from = simulation.world_to_screen(Vec2::new(9.5, 4.5));
+    let to = simulation.world_to_screen(Vec2::new(9.5, 5.5));
+    for input in InputState::swipe(from, to, 4) {
+        simulation.update_headless(DELTA, input);
+    }
+    // One more frame to see the cut
+    simulation.update_headless(DELTA, InputState::at(to));
+
+    assert_eq!(simulation.link_strains().count(), 1);
+    assert_eq!(simulation.nearest_link(Vec2::new(8.5, 5.0), 0.1), Some(0));
+    assert_eq!(simulation.nearest_link(Vec2::new(9.5, 5.0), 0.1), None);
+    let mut components = simulation.components().into_iter().map(|component| component.points).collect::<Vec<_>>();
+    components.sort();
+    assert_eq!(components, vec![vec![0, 1], vec![2]]);
+}